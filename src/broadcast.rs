@@ -0,0 +1,347 @@
+use alloc::{
+    alloc::{Layout, alloc, handle_alloc_error},
+    boxed::Box,
+};
+use core::{
+    cell::UnsafeCell,
+    error::Error,
+    fmt, hint,
+    mem::MaybeUninit,
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::cache::CachePadded;
+
+pub use crate::error::SendError;
+
+struct Meta {
+    // Allocation info. The `TX` bit is set while the sender is around, and
+    // the bits above it count live receivers in steps of `RECEIVER`.
+    // Whoever takes `state` to zero frees.
+    state: AtomicUsize,
+
+    // Queue info. Only the sender moves `head`; each receiver keeps its own
+    // position.
+    head: CachePadded<AtomicUsize>,
+}
+
+const TX: usize = 1;
+const RECEIVER: usize = 2;
+
+// Bits of `Slot::lock`. Readers count up in steps of `READER`.
+const WRITER: usize = 1;
+const READER: usize = 2;
+
+struct Slot<T> {
+    // Zero while nothing was ever written here, otherwise one past the
+    // position of the value in the slot.
+    seq: AtomicUsize,
+    // The sender overwrites values receivers may still be cloning, so each
+    // slot has its own reader-writer lock.
+    lock: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// Unlocks the slot even if cloning the value panics.
+struct ReadGuard<'a, T>(&'a Slot<T>);
+
+impl<T> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.0.lock.fetch_sub(READER, Ordering::Release);
+    }
+}
+
+impl<T> Slot<T> {
+    fn read_lock(&self) -> ReadGuard<'_, T> {
+        loop {
+            let state = self.lock.load(Ordering::Relaxed);
+            if state & WRITER == 0
+                && self
+                    .lock
+                    .compare_exchange_weak(
+                        state,
+                        state + READER,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            {
+                return ReadGuard(self);
+            }
+            hint::spin_loop();
+        }
+    }
+
+    fn write_lock(&self) {
+        while self
+            .lock
+            .compare_exchange_weak(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+    }
+
+    fn write_unlock(&self) {
+        self.lock.store(0, Ordering::Release);
+    }
+}
+
+#[repr(C)]
+struct Shared<T> {
+    meta: Meta,
+    buffer: [Slot<T>],
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        for slot in &mut self.buffer {
+            if *slot.seq.get_mut() != 0 {
+                unsafe { slot.value.get_mut().assume_init_drop() };
+            }
+        }
+    }
+}
+
+/// The error returned by [`Receiver::try_recv`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TryRecvError {
+    /// The receiver has seen every value, but the sender may still send more.
+    Empty,
+    /// The receiver has seen every value and the sender is gone.
+    Disconnected,
+    /// The receiver fell behind and this many values were overwritten before
+    /// it got to them. The next call picks up at the oldest value still
+    /// around.
+    Lagged(usize),
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("receiving on an empty channel"),
+            TryRecvError::Disconnected => {
+                f.write_str("receiving on an empty and disconnected channel")
+            }
+            TryRecvError::Lagged(n) => write!(f, "receiver lagged behind by {n} values"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+#[derive(Debug)]
+pub struct Sender<T> {
+    ptr: NonNull<Shared<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for Sender<T> {}
+
+impl<T> Sender<T> {
+    /// Sends `el` to every receiver, overwriting the oldest value if the
+    /// buffer is full.
+    ///
+    /// Fails only if every receiver is gone. May briefly wait for a receiver
+    /// that is cloning the value about to be overwritten.
+    pub fn try_send(&mut self, el: T) -> Result<(), SendError<T>> {
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.state.load(Ordering::Relaxed) < RECEIVER {
+            return Err(SendError(el));
+        }
+        let head = shared.meta.head.load(Ordering::Relaxed);
+        let slot = &shared.buffer[head % shared.buffer.len()];
+        slot.write_lock();
+        let value = unsafe { &mut *slot.value.get() };
+        if slot.seq.load(Ordering::Relaxed) != 0 {
+            unsafe { value.assume_init_drop() };
+        }
+        value.write(el);
+        slot.seq.store(head.wrapping_add(1), Ordering::Relaxed);
+        slot.write_unlock();
+        shared
+            .meta
+            .head
+            .store(head.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.state.fetch_and(!TX, Ordering::AcqRel) == TX {
+            drop(unsafe { Box::from_non_null(self.ptr) });
+        }
+    }
+}
+
+/// A receiver that sees every value the sender sends after it was created.
+///
+/// Cloning it gives a receiver at the same position.
+#[derive(Debug)]
+pub struct Receiver<T> {
+    ptr: NonNull<Shared<T>>,
+    pos: usize,
+}
+
+unsafe impl<T: Send + Sync> Send for Receiver<T> {}
+
+impl<T: Clone> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let shared = unsafe { self.ptr.as_ref() };
+        let cap = shared.buffer.len();
+        loop {
+            let head = shared.meta.head.load(Ordering::Acquire);
+            if head == self.pos {
+                if shared.meta.state.load(Ordering::Acquire) & TX != 0 {
+                    return Err(TryRecvError::Empty);
+                }
+                // The sender may have sent right before it dropped.
+                if shared.meta.head.load(Ordering::Acquire) == self.pos {
+                    return Err(TryRecvError::Disconnected);
+                }
+                continue;
+            }
+            let behind = head.wrapping_sub(self.pos);
+            if behind > cap {
+                let skipped = behind - cap;
+                self.pos = self.pos.wrapping_add(skipped);
+                return Err(TryRecvError::Lagged(skipped));
+            }
+            let slot = &shared.buffer[self.pos % cap];
+            let guard = slot.read_lock();
+            // The sender may have lapped us since we looked at `head`.
+            let el = (slot.seq.load(Ordering::Relaxed) == self.pos.wrapping_add(1))
+                .then(|| unsafe { (*slot.value.get()).assume_init_ref() }.clone());
+            drop(guard);
+            if let Some(el) = el {
+                self.pos = self.pos.wrapping_add(1);
+                return Ok(el);
+            }
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        unsafe { self.ptr.as_ref() }
+            .meta
+            .state
+            .fetch_add(RECEIVER, Ordering::Relaxed);
+        Receiver {
+            ptr: self.ptr,
+            pos: self.pos,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.state.fetch_sub(RECEIVER, Ordering::AcqRel) == RECEIVER {
+            drop(unsafe { Box::from_non_null(self.ptr) });
+        }
+    }
+}
+
+/// Creates a channel that keeps the latest `cap` values for every receiver.
+///
+/// A receiver that falls more than `cap` values behind misses the oldest
+/// ones and is told how many with [`TryRecvError::Lagged`].
+///
+/// # Panics
+///
+/// Panics if `cap` is zero.
+pub fn new<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(cap > 0, "queue capacity must be greater than zero");
+    let layout = Layout::new::<Meta>()
+        .extend(Layout::array::<Slot<T>>(cap).unwrap())
+        .unwrap();
+    let padded = layout.0.pad_to_align();
+    let ptr = NonNull::new(unsafe { alloc(padded) })
+        .unwrap_or_else(|| handle_alloc_error(padded))
+        .cast();
+    unsafe {
+        ptr.write(Meta {
+            state: (TX | RECEIVER).into(),
+            head: CachePadded(0.into()),
+        });
+        let buffer = ptr.byte_add(layout.1).cast::<Slot<T>>();
+        for i in 0..cap {
+            buffer.add(i).write(Slot {
+                seq: 0.into(),
+                lock: 0.into(),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            });
+        }
+    };
+    let thing = NonNull::from_raw_parts(ptr, cap);
+    (Sender { ptr: thing }, Receiver { ptr: thing, pos: 0 })
+}
+
+#[cfg(test)]
+mod test {
+    use std::prelude::rust_2024::*;
+    use std::thread;
+
+    use crate::broadcast::{SendError, TryRecvError, new};
+
+    #[test]
+    fn lagged() {
+        let (mut tx, mut fast) = new(4);
+        let mut slow = fast.clone();
+        for i in 0..10 {
+            tx.try_send(i.to_string()).unwrap();
+            assert_eq!(fast.try_recv(), Ok(i.to_string()));
+        }
+        assert_eq!(fast.try_recv(), Err(TryRecvError::Empty));
+        // Only the last four values are still around.
+        assert_eq!(slow.try_recv(), Err(TryRecvError::Lagged(6)));
+        for i in 6..10 {
+            assert_eq!(slow.try_recv(), Ok(i.to_string()));
+        }
+        drop(tx);
+        assert_eq!(slow.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(fast.try_recv(), Err(TryRecvError::Disconnected));
+
+        let (mut tx, rx) = new(4);
+        drop(rx);
+        assert_eq!(tx.try_send(1), Err(SendError(1)));
+    }
+
+    #[test]
+    fn concurrent() {
+        const N: usize = if cfg!(miri) { 200 } else { 20_000 };
+        let (mut tx, rx) = new(8);
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let mut rx = rx.clone();
+                thread::spawn(move || {
+                    let mut last = None;
+                    loop {
+                        match rx.try_recv() {
+                            // Values come in order, possibly with gaps.
+                            Ok(el) => {
+                                assert!(last.is_none_or(|prev| prev < el));
+                                last = Some(el);
+                            }
+                            Err(TryRecvError::Lagged(_) | TryRecvError::Empty) => {
+                                thread::yield_now()
+                            }
+                            Err(TryRecvError::Disconnected) => return last,
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(rx);
+        for i in 0..N {
+            tx.try_send(vec![i; 4].into_boxed_slice()).unwrap();
+        }
+        drop(tx);
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().as_deref(), Some(&[N - 1; 4][..]));
+        }
+    }
+}
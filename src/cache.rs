@@ -0,0 +1,14 @@
+use core::ops::Deref;
+
+/// Aligns its contents to a cache line so that it never shares one with a
+/// neighbouring field.
+#[repr(align(64))]
+pub(crate) struct CachePadded<T>(pub(crate) T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
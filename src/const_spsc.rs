@@ -0,0 +1,335 @@
+use alloc::boxed::Box;
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::cache::CachePadded;
+
+pub use crate::error::{TryRecvError, TrySendError};
+
+struct Meta {
+    // Connection info, reset on every split. Each end clears its bit when it
+    // is dropped.
+    state: AtomicUsize,
+
+    // Queue info
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+}
+
+const TX: usize = 1 << 0;
+const RX: usize = 1 << 1;
+
+impl Meta {
+    const fn new() -> Self {
+        Meta {
+            state: AtomicUsize::new(TX | RX),
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+        }
+    }
+
+    fn is_present(&self, side: usize) -> bool {
+        self.state.load(Ordering::Acquire) & side != 0
+    }
+}
+
+// Drops the elements between `tail` and `head`.
+//
+// # Safety
+//
+// Neither end may be using the queue anymore.
+unsafe fn drop_queued<T>(meta: &Meta, buffer: &[UnsafeCell<MaybeUninit<T>>]) {
+    let head = meta.head.load(Ordering::Relaxed);
+    let mut tail = meta.tail.load(Ordering::Relaxed);
+    while tail != head {
+        unsafe { (*buffer[tail % buffer.len()].get()).assume_init_drop() };
+        tail = tail.wrapping_add(1);
+    }
+}
+
+// Clears `side` from the state, and frees `meta` and whatever is still
+// queued if it was a side allocation and the other end is gone too.
+//
+// # Safety
+//
+// Must be called once, by the end `side` names, as it goes away.
+unsafe fn release<T>(
+    meta: NonNull<Meta>,
+    buffer: &[UnsafeCell<MaybeUninit<T>>],
+    side: usize,
+    in_buffer: bool,
+) {
+    let state = unsafe { meta.as_ref() }
+        .state
+        .fetch_and(!side, Ordering::AcqRel);
+    if in_buffer && state & !side == 0 {
+        let meta = unsafe { Box::from_non_null(meta) };
+        unsafe { drop_queued(&meta, buffer) };
+    }
+}
+
+/// A queue of up to `N` elements stored inline, without any heap allocation.
+///
+/// Use [`Queue::split`] to get a sender and receiver borrowing the queue.
+pub struct Queue<T, const N: usize> {
+    meta: Meta,
+    buffer: [UnsafeCell<MaybeUninit<T>>; N],
+}
+
+impl<T, const N: usize> Queue<T, N> {
+    /// Creates an empty queue.
+    ///
+    /// Fails to compile if `N` is zero.
+    pub const fn new() -> Self {
+        const { assert!(N > 0, "queue capacity must be greater than zero") };
+        Self {
+            meta: Meta::new(),
+            buffer: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+        }
+    }
+
+    /// Splits the queue into a sender and a receiver.
+    ///
+    /// Both borrow the queue, so it can't move while they are around.
+    /// Elements left over when they are dropped stay queued until the next
+    /// split.
+    pub fn split(&mut self) -> (Sender<'_, T>, Receiver<'_, T>) {
+        *self.meta.state.get_mut() = TX | RX;
+        let (meta, buffer) = (NonNull::from(&self.meta), &self.buffer[..]);
+        split(meta, buffer, false)
+    }
+}
+
+impl<T, const N: usize> Default for Queue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for Queue<T, N> {
+    fn drop(&mut self) {
+        unsafe { drop_queued(&self.meta, &self.buffer) };
+    }
+}
+
+/// Creates a queue over `storage`, using its slots for the elements.
+///
+/// Both ends borrow `storage`, so it can come from a pool or a static, or
+/// live on the stack with the ends handed to scoped threads. Only the
+/// positions go in a small allocation of their own, which the last end to
+/// be dropped frees, together with any elements still queued.
+///
+/// # Panics
+///
+/// Panics if `storage` is empty.
+pub fn new_in_buffer<T>(storage: &mut [MaybeUninit<T>]) -> (Sender<'_, T>, Receiver<'_, T>) {
+    assert!(
+        !storage.is_empty(),
+        "queue capacity must be greater than zero"
+    );
+    let meta = Box::into_non_null(Box::new(Meta::new()));
+    // SAFETY: `UnsafeCell` is `repr(transparent)`, and `storage` is borrowed
+    // mutably for as long as the ends are around.
+    let buffer =
+        unsafe { &*(storage as *mut [MaybeUninit<T>] as *const [UnsafeCell<MaybeUninit<T>>]) };
+    split(meta, buffer, true)
+}
+
+fn split<T>(
+    meta: NonNull<Meta>,
+    buffer: &[UnsafeCell<MaybeUninit<T>>],
+    in_buffer: bool,
+) -> (Sender<'_, T>, Receiver<'_, T>) {
+    (
+        Sender {
+            meta,
+            buffer,
+            in_buffer,
+        },
+        Receiver {
+            meta,
+            buffer,
+            in_buffer,
+        },
+    )
+}
+
+pub struct Sender<'a, T> {
+    meta: NonNull<Meta>,
+    buffer: &'a [UnsafeCell<MaybeUninit<T>>],
+    // Whether `meta` was allocated by `new_in_buffer`, rather than being
+    // part of a `Queue`.
+    in_buffer: bool,
+}
+
+unsafe impl<T: Send> Send for Sender<'_, T> {}
+
+impl<T> Sender<'_, T> {
+    pub fn try_send(&mut self, el: T) -> Result<(), TrySendError<T>> {
+        let meta = unsafe { self.meta.as_ref() };
+        // Nothing will take `el` out before the next split, even if there is
+        // room for it.
+        if !meta.is_present(RX) {
+            return Err(TrySendError::Disconnected(el));
+        }
+        let head = meta.head.load(Ordering::Relaxed);
+        let tail = meta.tail.load(Ordering::Acquire);
+        if head.wrapping_sub(tail) == self.buffer.len() {
+            Err(TrySendError::Full(el))
+        } else {
+            let slot = &self.buffer[head % self.buffer.len()];
+            unsafe { (*slot.get()).write(el) };
+            meta.head.store(head.wrapping_add(1), Ordering::Release);
+            Ok(())
+        }
+    }
+}
+
+impl<T> Drop for Sender<'_, T> {
+    fn drop(&mut self) {
+        unsafe { release(self.meta, self.buffer, TX, self.in_buffer) };
+    }
+}
+
+pub struct Receiver<'a, T> {
+    meta: NonNull<Meta>,
+    buffer: &'a [UnsafeCell<MaybeUninit<T>>],
+    in_buffer: bool,
+}
+
+unsafe impl<T: Send> Send for Receiver<'_, T> {}
+
+impl<T> Receiver<'_, T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let meta = unsafe { self.meta.as_ref() };
+        let tail = meta.tail.load(Ordering::Relaxed);
+        let mut head = meta.head.load(Ordering::Acquire);
+        if tail == head {
+            if meta.is_present(TX) {
+                return Err(TryRecvError::Empty);
+            }
+            head = meta.head.load(Ordering::Acquire);
+            if tail == head {
+                return Err(TryRecvError::Disconnected);
+            }
+        }
+        let slot = &self.buffer[tail % self.buffer.len()];
+        let el = unsafe { (*slot.get()).assume_init_read() };
+        meta.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(el)
+    }
+}
+
+impl<T> Drop for Receiver<'_, T> {
+    fn drop(&mut self) {
+        unsafe { release(self.meta, self.buffer, RX, self.in_buffer) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::mem::MaybeUninit;
+    use std::{prelude::rust_2024::*, thread};
+
+    use crate::const_spsc::{Queue, TryRecvError, TrySendError, new_in_buffer};
+
+    #[test]
+    fn scoped() {
+        const N: u32 = if cfg!(miri) { 500 } else { 10_000 };
+        let mut queue = Queue::<u32, 8>::new();
+        let (mut tx, mut rx) = queue.split();
+        thread::scope(|s| {
+            s.spawn(move || {
+                for mut el in 0..N {
+                    while let Err(e) = tx.try_send(el) {
+                        el = e.into_inner();
+                        thread::yield_now();
+                    }
+                }
+            });
+            s.spawn(move || {
+                let mut expected = 0;
+                loop {
+                    match rx.try_recv() {
+                        Ok(el) => {
+                            assert_eq!(el, expected);
+                            expected += 1;
+                        }
+                        Err(TryRecvError::Empty) => thread::yield_now(),
+                        Err(TryRecvError::Disconnected) => break,
+                    }
+                }
+                assert_eq!(expected, N);
+            });
+        });
+
+        // The queue is whole again once both halves are gone.
+        let (mut tx, mut rx) = queue.split();
+        tx.try_send(1).unwrap();
+        assert_eq!(rx.try_recv(), Ok(1));
+    }
+
+    #[test]
+    fn receiver_gone() {
+        let mut queue = Queue::<u32, 4>::new();
+        let (mut tx, rx) = queue.split();
+        tx.try_send(1).unwrap();
+        drop(rx);
+        // There is room, but nobody to receive it.
+        assert_eq!(tx.try_send(2), Err(TrySendError::Disconnected(2)));
+    }
+
+    #[test]
+    fn in_buffer() {
+        const N: u32 = if cfg!(miri) { 500 } else { 10_000 };
+        let mut storage = [MaybeUninit::<u32>::uninit(); 8];
+        let (mut tx, mut rx) = new_in_buffer(&mut storage);
+        thread::scope(|s| {
+            s.spawn(move || {
+                for mut el in 0..N {
+                    while let Err(e) = tx.try_send(el) {
+                        el = e.into_inner();
+                        thread::yield_now();
+                    }
+                }
+            });
+            s.spawn(move || {
+                let mut expected = 0;
+                loop {
+                    match rx.try_recv() {
+                        Ok(el) => {
+                            assert_eq!(el, expected);
+                            expected += 1;
+                        }
+                        Err(TryRecvError::Empty) => thread::yield_now(),
+                        Err(TryRecvError::Disconnected) => break,
+                    }
+                }
+                assert_eq!(expected, N);
+            });
+        });
+
+        // The storage is free to build another queue over.
+        let (mut tx, rx) = new_in_buffer(&mut storage);
+        tx.try_send(1).unwrap();
+        drop(rx);
+        assert_eq!(tx.try_send(2), Err(TrySendError::Disconnected(2)));
+    }
+
+    #[test]
+    fn in_buffer_leftovers() {
+        let mut storage = [const { MaybeUninit::<String>::uninit() }; 4];
+        let (mut tx, mut rx) = new_in_buffer(&mut storage);
+        for i in 0..4 {
+            tx.try_send(i.to_string()).unwrap();
+        }
+        assert_eq!(rx.try_recv().as_deref(), Ok("0"));
+        // Whichever end goes last drops the other three.
+        drop(rx);
+        drop(tx);
+    }
+}
@@ -0,0 +1,171 @@
+use alloc::boxed::Box;
+use core::mem::MaybeUninit;
+
+/// A fixed-capacity ring buffer that takes elements at both ends.
+///
+/// Unlike the queues in this crate it has a single owner, so nothing is
+/// atomic and there is no sender or receiver to split off. Share it behind a
+/// lock if several threads need it.
+#[derive(Debug)]
+pub struct Deque<T> {
+    buffer: Box<[MaybeUninit<T>]>,
+    // Index of the front element, and how many follow it, wrapping around
+    // the end of the buffer.
+    front: usize,
+    len: usize,
+}
+
+impl<T> Deque<T> {
+    /// Creates an empty deque with room for `cap` elements.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cap` is zero.
+    pub fn new(cap: usize) -> Self {
+        assert!(cap > 0, "queue capacity must be greater than zero");
+        Deque {
+            buffer: Box::new_uninit_slice(cap),
+            front: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the index of the slot `n` slots after the front.
+    fn index(&self, n: usize) -> usize {
+        (self.front + n) % self.buffer.len()
+    }
+
+    /// Appends an element, handing it back if the deque is full.
+    pub fn push_back(&mut self, el: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(el);
+        }
+        let i = self.index(self.len);
+        self.buffer[i].write(el);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Puts an element in front of everything already queued, handing it
+    /// back if the deque is full.
+    ///
+    /// Meant for urgent elements that should skip ahead of the backlog.
+    pub fn push_front(&mut self, el: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(el);
+        }
+        self.front = self.index(self.buffer.len() - 1);
+        self.buffer[self.front].write(el);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the front element.
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let el = unsafe { self.buffer[self.front].assume_init_read() };
+        self.front = self.index(1);
+        self.len -= 1;
+        Some(el)
+    }
+
+    /// Removes the back element.
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        let i = self.index(self.len);
+        Some(unsafe { self.buffer[i].assume_init_read() })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == self.buffer.len()
+    }
+}
+
+impl<T> Drop for Deque<T> {
+    fn drop(&mut self) {
+        for n in 0..self.len {
+            let i = self.index(n);
+            unsafe { self.buffer[i].assume_init_drop() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::prelude::rust_2024::*;
+
+    use crate::deque::Deque;
+
+    #[test]
+    fn urgent() {
+        let mut deque = Deque::new(4);
+        deque.push_back(1).unwrap();
+        deque.push_back(2).unwrap();
+        deque.push_front(0).unwrap();
+        assert_eq!(deque.pop_front(), Some(0));
+        assert_eq!(deque.pop_front(), Some(1));
+        deque.push_back(3).unwrap();
+        deque.push_front(9).unwrap();
+        deque.push_front(8).unwrap();
+        assert_eq!(deque.push_front(7), Err(7));
+        assert_eq!(deque.push_back(7), Err(7));
+        let rest: Vec<_> = core::iter::from_fn(|| deque.pop_front()).collect();
+        assert_eq!(rest, [8, 9, 2, 3]);
+    }
+
+    #[test]
+    fn both_ends() {
+        let mut deque = Deque::new(3);
+        assert_eq!(deque.pop_back(), None);
+        deque.push_back(1).unwrap();
+        assert_eq!(deque.pop_back(), Some(1));
+        assert!(deque.is_empty());
+        // Walk the front backwards past the start of the buffer, and the back
+        // forwards past its end, a few times over.
+        for lap in 0..4 {
+            deque.push_front(lap * 10 + 1).unwrap();
+            deque.push_front(lap * 10).unwrap();
+            deque.push_back(lap * 10 + 2).unwrap();
+            assert!(deque.is_full());
+            assert_eq!(deque.push_back(99), Err(99));
+            assert_eq!(deque.pop_back(), Some(lap * 10 + 2));
+            assert_eq!(deque.pop_front(), Some(lap * 10));
+            assert_eq!(deque.pop_back(), Some(lap * 10 + 1));
+            assert!(deque.is_empty());
+            assert_eq!(deque.pop_front(), None);
+            deque.push_back(0).unwrap();
+            deque.push_back(0).unwrap();
+            assert_eq!(deque.pop_front(), Some(0));
+            assert_eq!(deque.pop_back(), Some(0));
+        }
+    }
+
+    #[test]
+    fn drop_queued() {
+        let mut deque = Deque::new(3);
+        for s in ["a", "b", "c"] {
+            deque.push_front(s.to_string()).unwrap();
+        }
+        assert_eq!(deque.pop_front().as_deref(), Some("c"));
+        deque.push_back("d".to_string()).unwrap();
+        // Miri checks that the three left over are dropped.
+        drop(deque);
+    }
+}
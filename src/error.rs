@@ -0,0 +1,158 @@
+use core::{error::Error, fmt};
+
+/// The error returned by `try_send`.
+///
+/// Both variants hand the element back to the caller.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TrySendError<T> {
+    /// The queue is full; the element may be sent again later.
+    Full(T),
+    /// The receiver is gone, so the element can never be received.
+    Disconnected(T),
+}
+
+impl<T> TrySendError<T> {
+    /// Returns the element that could not be sent.
+    pub fn into_inner(self) -> T {
+        match self {
+            TrySendError::Full(el) | TrySendError::Disconnected(el) => el,
+        }
+    }
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(..) => f.write_str("Full(..)"),
+            TrySendError::Disconnected(..) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(..) => f.write_str("sending on a full queue"),
+            TrySendError::Disconnected(..) => f.write_str("sending on a disconnected queue"),
+        }
+    }
+}
+
+impl<T> Error for TrySendError<T> {}
+
+/// The error returned by `try_recv`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TryRecvError {
+    /// The queue is empty, but the sender may still send more.
+    Empty,
+    /// The queue is empty and the sender is gone.
+    Disconnected,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("receiving on an empty queue"),
+            TryRecvError::Disconnected => {
+                f.write_str("receiving on an empty and disconnected queue")
+            }
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+/// The error returned by `send` when the receiver is gone.
+///
+/// The element that could not be sent is handed back.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SendError(..)")
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sending on a disconnected queue")
+    }
+}
+
+impl<T> Error for SendError<T> {}
+
+/// The error returned by `recv` when the queue is empty and the sender is
+/// gone.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("receiving on an empty and disconnected queue")
+    }
+}
+
+impl Error for RecvError {}
+
+/// The error returned by `send_timeout`.
+///
+/// Both variants hand the element back to the caller.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum SendTimeoutError<T> {
+    /// The queue stayed full until the timeout elapsed.
+    Timeout(T),
+    /// The receiver is gone, so the element can never be received.
+    Disconnected(T),
+}
+
+impl<T> SendTimeoutError<T> {
+    /// Returns the element that could not be sent.
+    pub fn into_inner(self) -> T {
+        match self {
+            SendTimeoutError::Timeout(el) | SendTimeoutError::Disconnected(el) => el,
+        }
+    }
+}
+
+impl<T> fmt::Debug for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(..) => f.write_str("Timeout(..)"),
+            SendTimeoutError::Disconnected(..) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(..) => f.write_str("timed out waiting on send operation"),
+            SendTimeoutError::Disconnected(..) => f.write_str("sending on a disconnected queue"),
+        }
+    }
+}
+
+impl<T> Error for SendTimeoutError<T> {}
+
+/// The error returned by `recv_timeout`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RecvTimeoutError {
+    /// The queue stayed empty until the timeout elapsed.
+    Timeout,
+    /// The queue is empty and the sender is gone.
+    Disconnected,
+}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout => f.write_str("timed out waiting on receive operation"),
+            RecvTimeoutError::Disconnected => {
+                f.write_str("receiving on an empty and disconnected queue")
+            }
+        }
+    }
+}
+
+impl Error for RecvTimeoutError {}
@@ -1,3 +1,22 @@
-#![feature(ptr_metadata, box_vec_non_null, generic_atomic)]
+#![no_std]
+#![feature(allocator_api, ptr_metadata, box_vec_non_null)]
 
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+pub mod broadcast;
+mod cache;
+pub mod const_spsc;
+pub mod deque;
+mod error;
+pub mod mpmc;
+pub mod mpsc;
+pub mod overwrite;
+pub mod priority;
+pub mod recycle;
 pub mod spsc;
+mod sync;
+pub mod unbounded;
+mod waker;
+pub mod watch;
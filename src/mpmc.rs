@@ -0,0 +1,337 @@
+use alloc::{
+    alloc::{Layout, alloc, handle_alloc_error},
+    boxed::Box,
+};
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::cache::CachePadded;
+
+pub use crate::error::{TryRecvError, TrySendError};
+
+struct Meta {
+    // Allocation info. The low half of `state` counts live senders and the
+    // high half live receivers. Whoever takes it to zero frees.
+    state: AtomicUsize,
+
+    // Queue info. Producers race on `head` and consumers on `tail`, so each
+    // gets its own cache line.
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+}
+
+const SENDER: usize = 1;
+const RECEIVER: usize = 1 << (usize::BITS / 2);
+
+// Also used by `mpsc::fair`.
+pub(crate) struct Slot<T> {
+    // `2 * pos` when the slot is free for the producer claiming position
+    // `pos`, one more once that producer has written it, and
+    // `2 * (pos + capacity)` once the consumer has taken the value out again.
+    // Doubling keeps "written for `pos`" and "free for `pos + 1`" apart when
+    // the capacity is one.
+    pub(crate) seq: AtomicUsize,
+    pub(crate) value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Slot<T> {
+    /// Returns an empty slot, free for position `pos`.
+    pub(crate) fn new(pos: usize) -> Self {
+        Slot {
+            seq: free(pos).into(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+}
+
+#[repr(C)]
+struct Shared<T> {
+    meta: Meta,
+    buffer: [Slot<T>],
+}
+
+pub(crate) fn free(pos: usize) -> usize {
+    pos.wrapping_mul(2)
+}
+
+pub(crate) fn written(pos: usize) -> usize {
+    free(pos) + 1
+}
+
+impl<T> Shared<T> {
+    fn slot(&self, pos: usize) -> &Slot<T> {
+        &self.buffer[pos % self.buffer.len()]
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        // Every producer finishes writing before it drops, so each claimed
+        // slot between `tail` and `head` is initialized.
+        let head = *self.meta.head.0.get_mut();
+        let mut tail = *self.meta.tail.0.get_mut();
+        while tail != head {
+            let len = self.buffer.len();
+            unsafe { self.buffer[tail % len].value.get_mut().assume_init_drop() };
+            tail = tail.wrapping_add(1);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Sender<T> {
+    ptr: NonNull<Shared<T>>,
+}
+
+unsafe impl<T: Send> Send for Sender<T> {}
+
+impl<T> Sender<T> {
+    pub fn try_send(&mut self, el: T) -> Result<(), TrySendError<T>> {
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.state.load(Ordering::Relaxed) < RECEIVER {
+            return Err(TrySendError::Disconnected(el));
+        }
+        let mut head = shared.meta.head.load(Ordering::Relaxed);
+        loop {
+            let slot = shared.slot(head);
+            let seq = slot.seq.load(Ordering::Acquire);
+            match (seq.wrapping_sub(free(head)) as isize).signum() {
+                // The slot is free; claim it. Other producers may be racing
+                // for it.
+                0 => match shared.meta.head.compare_exchange_weak(
+                    head,
+                    head.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).write(el) };
+                        slot.seq.store(written(head), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => head = current,
+                },
+                // The consumer of the previous lap hasn't taken its value out.
+                -1 => return Err(TrySendError::Full(el)),
+                // Another producer claimed the slot since we read `head`.
+                _ => head = shared.meta.head.load(Ordering::Relaxed),
+            }
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        unsafe { self.ptr.as_ref() }
+            .meta
+            .state
+            .fetch_add(SENDER, Ordering::Relaxed);
+        Sender { ptr: self.ptr }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.state.fetch_sub(SENDER, Ordering::AcqRel) == SENDER {
+            drop(unsafe { Box::from_non_null(self.ptr) });
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Receiver<T> {
+    ptr: NonNull<Shared<T>>,
+}
+
+unsafe impl<T: Send> Send for Receiver<T> {}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let shared = unsafe { self.ptr.as_ref() };
+        let mut tail = shared.meta.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = shared.slot(tail);
+            let seq = slot.seq.load(Ordering::Acquire);
+            match (seq.wrapping_sub(written(tail)) as isize).signum() {
+                // The slot holds a value; claim it. Other consumers may be
+                // racing for it.
+                0 => match shared.meta.tail.compare_exchange_weak(
+                    tail,
+                    tail.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let el = unsafe { (*slot.value.get()).assume_init_read() };
+                        // Hand the slot to the producer of the next lap.
+                        slot.seq.store(
+                            free(tail.wrapping_add(shared.buffer.len())),
+                            Ordering::Release,
+                        );
+                        return Ok(el);
+                    }
+                    Err(current) => tail = current,
+                },
+                -1 => {
+                    if shared.meta.state.load(Ordering::Acquire) % RECEIVER != 0 {
+                        return Err(TryRecvError::Empty);
+                    }
+                    // The last sender may have written right before it
+                    // dropped.
+                    if slot.seq.load(Ordering::Acquire) == seq {
+                        return Err(TryRecvError::Disconnected);
+                    }
+                }
+                // Another consumer took the slot since we read `tail`.
+                _ => tail = shared.meta.tail.load(Ordering::Relaxed),
+            }
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        unsafe { self.ptr.as_ref() }
+            .meta
+            .state
+            .fetch_add(RECEIVER, Ordering::Relaxed);
+        Receiver { ptr: self.ptr }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.state.fetch_sub(RECEIVER, Ordering::AcqRel) == RECEIVER {
+            drop(unsafe { Box::from_non_null(self.ptr) });
+        }
+    }
+}
+
+/// Creates a queue that can hold up to `cap` elements and accepts any number
+/// of senders and receivers.
+///
+/// Each element goes to exactly one of the receivers.
+///
+/// # Panics
+///
+/// Panics if `cap` is zero.
+pub fn new<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(cap > 0, "queue capacity must be greater than zero");
+    let layout = Layout::new::<Meta>()
+        .extend(Layout::array::<Slot<T>>(cap).unwrap())
+        .unwrap();
+    let padded = layout.0.pad_to_align();
+    let ptr = NonNull::new(unsafe { alloc(padded) })
+        .unwrap_or_else(|| handle_alloc_error(padded))
+        .cast();
+    unsafe {
+        ptr.write(Meta {
+            state: (SENDER | RECEIVER).into(),
+            head: CachePadded(0.into()),
+            tail: CachePadded(0.into()),
+        });
+        let buffer = ptr.byte_add(layout.1).cast::<Slot<T>>();
+        for i in 0..cap {
+            buffer.add(i).write(Slot::new(i));
+        }
+    };
+    let thing = NonNull::from_raw_parts(ptr, cap);
+    (Sender { ptr: thing }, Receiver { ptr: thing })
+}
+
+#[cfg(test)]
+mod test {
+    use std::prelude::rust_2024::*;
+    use std::thread;
+
+    use crate::mpmc::{TryRecvError, TrySendError, new};
+
+    #[test]
+    fn laps() {
+        let (mut tx, mut rx) = new(3);
+        for lap in 0..4 {
+            for i in 0..3 {
+                tx.try_send(lap * 3 + i).unwrap();
+            }
+            assert_eq!(tx.try_send(99), Err(TrySendError::Full(99)));
+            let mut other = rx.clone();
+            assert_eq!(rx.try_recv(), Ok(lap * 3));
+            assert_eq!(other.try_recv(), Ok(lap * 3 + 1));
+            assert_eq!(rx.try_recv(), Ok(lap * 3 + 2));
+            assert_eq!(other.try_recv(), Err(TryRecvError::Empty));
+        }
+        tx.try_send(12).unwrap();
+        drop(tx);
+        assert_eq!(rx.try_recv(), Ok(12));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+
+        let (mut tx, rx) = new(3);
+        drop(rx);
+        assert_eq!(tx.try_send(1), Err(TrySendError::Disconnected(1)));
+    }
+
+    #[test]
+    fn single_slot() {
+        let (mut tx, mut rx) = new(1);
+        for i in 0..3 {
+            tx.try_send(i).unwrap();
+            assert_eq!(tx.try_send(99), Err(TrySendError::Full(99)));
+            assert_eq!(rx.try_recv(), Ok(i));
+            assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        }
+    }
+
+    #[test]
+    fn stress() {
+        const PRODUCERS: usize = 4;
+        const CONSUMERS: usize = 4;
+        const N: usize = if cfg!(miri) { 200 } else { 100_000 };
+        let (tx, rx) = new::<usize>(64);
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let mut tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..N {
+                        let mut el = p * N + i;
+                        while let Err(e) = tx.try_send(el) {
+                            el = e.into_inner();
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..CONSUMERS)
+            .map(|_| {
+                let mut rx = rx.clone();
+                thread::spawn(move || {
+                    let mut got = Vec::new();
+                    loop {
+                        match rx.try_recv() {
+                            Ok(el) => got.push(el),
+                            Err(TryRecvError::Empty) => thread::yield_now(),
+                            Err(TryRecvError::Disconnected) => return got,
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop((tx, rx));
+        for handle in producers {
+            handle.join().unwrap();
+        }
+        let mut all: Vec<_> = consumers
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        all.sort_unstable();
+        // Every element arrived, and arrived once.
+        assert!(all.iter().copied().eq(0..PRODUCERS * N));
+    }
+}
@@ -0,0 +1,382 @@
+use alloc::{
+    alloc::{Layout, alloc, handle_alloc_error},
+    boxed::Box,
+};
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::cache::CachePadded;
+
+pub use crate::error::{TryRecvError, TrySendError};
+
+pub mod fair;
+
+struct Meta {
+    // Allocation info. The `RX` bit is set while the receiver is around, and
+    // the bits above it count live senders in steps of `SENDER`. Whoever
+    // takes `state` to zero frees.
+    state: AtomicUsize,
+
+    // Queue info. The producer writes `head` while the consumer writes `tail`,
+    // so each gets its own cache line.
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+}
+
+const RX: usize = 1;
+const SENDER: usize = 2;
+
+struct Slot<T> {
+    // Set by the producer that claimed the slot once the value is written,
+    // cleared by the receiver once it has taken the value out.
+    ready: AtomicBool,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+#[repr(C)]
+struct Shared<T> {
+    meta: Meta,
+    buffer: [Slot<T>],
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        // Every producer finishes writing before it drops, so each claimed
+        // slot between `tail` and `head` is initialized.
+        let head = *self.meta.head.0.get_mut();
+        let mut tail = *self.meta.tail.0.get_mut();
+        while tail != head {
+            let len = self.buffer.len();
+            unsafe { self.buffer[tail % len].value.get_mut().assume_init_drop() };
+            tail = tail.wrapping_add(1);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Sender<T> {
+    ptr: NonNull<Shared<T>>,
+}
+
+unsafe impl<T: Send> Send for Sender<T> {}
+
+impl<T> Sender<T> {
+    pub fn try_send(&mut self, el: T) -> Result<(), TrySendError<T>> {
+        self.send_shared(el)
+    }
+
+    /// Turns the sender into one that can send through a shared reference.
+    pub fn into_sync(self) -> SyncSender<T> {
+        SyncSender { tx: self }
+    }
+
+    // Producers only ever race through atomics, so sending doesn't actually
+    // need exclusive access.
+    fn send_shared(&self, el: T) -> Result<(), TrySendError<T>> {
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.state.load(Ordering::Relaxed) & RX == 0 {
+            return Err(TrySendError::Disconnected(el));
+        }
+        let mut head = shared.meta.head.load(Ordering::Relaxed);
+        loop {
+            let tail = shared.meta.tail.load(Ordering::Acquire);
+            // `tail` may be older than `head` from another producer's point
+            // of view, which makes the distance look larger than it is.
+            if head.wrapping_sub(tail) >= shared.buffer.len() {
+                return Err(TrySendError::Full(el));
+            }
+            // Claim the slot at `head`. Other producers may be racing for it.
+            match shared.meta.head.compare_exchange_weak(
+                head,
+                head.wrapping_add(1),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+        // The receiver goes by `ready` rather than `head`, so it won't look at
+        // the slot until the value is in.
+        let slot = &shared.buffer[head % shared.buffer.len()];
+        unsafe { (*slot.value.get()).write(el) };
+        slot.ready.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        unsafe { self.ptr.as_ref() }
+            .meta
+            .state
+            .fetch_add(SENDER, Ordering::Relaxed);
+        Sender { ptr: self.ptr }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.state.fetch_sub(SENDER, Ordering::AcqRel) == SENDER {
+            drop(unsafe { Box::from_non_null(self.ptr) });
+        }
+    }
+}
+
+/// A sender that is [`Sync`], returned by [`Sender::into_sync`].
+///
+/// Any number of threads can send through a `&SyncSender` at once; it goes
+/// through the same claim on `head` as cloned senders do. The spsc sender
+/// has no such variant, since it relies on being the only producer.
+///
+/// There is no blocking `send`, for the same reason [`Sender`] has none:
+/// this queue keeps no wakers, so a full queue can only be retried.
+#[derive(Debug)]
+pub struct SyncSender<T> {
+    tx: Sender<T>,
+}
+
+impl<T> Clone for SyncSender<T> {
+    fn clone(&self) -> Self {
+        SyncSender {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+unsafe impl<T: Send> Sync for SyncSender<T> {}
+
+impl<T> SyncSender<T> {
+    pub fn try_send(&self, el: T) -> Result<(), TrySendError<T>> {
+        self.tx.send_shared(el)
+    }
+
+    /// Unwraps the sender.
+    pub fn into_inner(self) -> Sender<T> {
+        self.tx
+    }
+}
+
+#[derive(Debug)]
+pub struct Receiver<T> {
+    ptr: NonNull<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let shared = unsafe { self.ptr.as_ref() };
+        let tail = shared.meta.tail.load(Ordering::Relaxed);
+        let slot = &shared.buffer[tail % shared.buffer.len()];
+        if !slot.ready.load(Ordering::Acquire) {
+            if shared.meta.state.load(Ordering::Acquire) >= SENDER {
+                return Err(TryRecvError::Empty);
+            }
+            // The last sender may have written right before it dropped.
+            if !slot.ready.load(Ordering::Acquire) {
+                return Err(TryRecvError::Disconnected);
+            }
+        }
+        let el = unsafe { (*slot.value.get()).assume_init_read() };
+        slot.ready.store(false, Ordering::Relaxed);
+        shared
+            .meta
+            .tail
+            .store(tail.wrapping_add(1), Ordering::Release);
+        Ok(el)
+    }
+}
+
+unsafe impl<T: Send> Send for Receiver<T> {}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let shared = unsafe { self.ptr.as_ref() };
+        // Checking the senders and clearing `RX` in one step leaves no window
+        // where the last sender and the receiver both think the other frees.
+        if shared.meta.state.fetch_and(!RX, Ordering::AcqRel) == RX {
+            drop(unsafe { Box::from_non_null(self.ptr) });
+        }
+    }
+}
+
+/// Creates a queue that can hold up to `cap` elements and accepts any number
+/// of senders.
+///
+/// # Panics
+///
+/// Panics if `cap` is zero.
+pub fn new<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(cap > 0, "queue capacity must be greater than zero");
+    let layout = Layout::new::<Meta>()
+        .extend(Layout::array::<Slot<T>>(cap).unwrap())
+        .unwrap();
+    let padded = layout.0.pad_to_align();
+    let ptr = NonNull::new(unsafe { alloc(padded) })
+        .unwrap_or_else(|| handle_alloc_error(padded))
+        .cast();
+    unsafe {
+        ptr.write(Meta {
+            state: (SENDER | RX).into(),
+            head: CachePadded(0.into()),
+            tail: CachePadded(0.into()),
+        });
+        let buffer = ptr.byte_add(layout.1).cast::<Slot<T>>();
+        for i in 0..cap {
+            buffer.add(i).write(Slot {
+                ready: false.into(),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            });
+        }
+    };
+    let thing = NonNull::from_raw_parts(ptr, cap);
+    (Sender { ptr: thing }, Receiver { ptr: thing })
+}
+
+#[cfg(test)]
+mod test {
+    use std::prelude::rust_2024::*;
+    use std::{
+        collections::HashSet,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        thread,
+    };
+
+    use crate::mpsc::{TryRecvError, TrySendError, new};
+
+    #[test]
+    fn producers() {
+        const PRODUCERS: usize = 4;
+        const N: usize = if cfg!(miri) { 500 } else { 50_000 };
+        let (tx, mut rx) = new::<usize>(16);
+        let handles: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let mut tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..N {
+                        let mut el = p * N + i;
+                        while let Err(e) = tx.try_send(el) {
+                            el = e.into_inner();
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+
+        let mut seen = HashSet::new();
+        let mut last = [None; PRODUCERS];
+        loop {
+            match rx.try_recv() {
+                Ok(el) => {
+                    assert!(seen.insert(el), "duplicate {el}");
+                    // Each producer's items arrive in the order it sent them.
+                    let (p, i) = (el / N, el % N);
+                    assert!(last[p].is_none_or(|prev| prev < i));
+                    last[p] = Some(i);
+                }
+                Err(TryRecvError::Empty) => thread::yield_now(),
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        assert_eq!(seen.len(), PRODUCERS * N);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn drop_race() {
+        struct DropCounter(Arc<AtomicUsize>);
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        const N: usize = if cfg!(miri) { 100 } else { 10_000 };
+        let drops = Arc::new(AtomicUsize::new(0));
+        for _ in 0..N {
+            let (mut tx, rx) = new(2);
+            tx.try_send(DropCounter(drops.clone())).unwrap();
+            let other = tx.clone();
+            let handles = [
+                thread::spawn(move || drop(tx)),
+                thread::spawn(move || drop(other)),
+            ];
+            drop(rx);
+            for handle in handles {
+                handle.join().unwrap();
+            }
+        }
+        // Every allocation was freed, and freed once.
+        assert_eq!(drops.load(Ordering::Relaxed), N);
+    }
+
+    #[test]
+    fn disconnect() {
+        let (mut tx, rx) = new(4);
+        drop(rx);
+        assert_eq!(tx.try_send(1), Err(TrySendError::Disconnected(1)));
+
+        let (mut tx, mut rx) = new(4);
+        let mut other = tx.clone();
+        tx.try_send(1).unwrap();
+        drop(tx);
+        // One sender is still around.
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        other.try_send(2).unwrap();
+        drop(other);
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn sync_sender() {
+        const N: usize = if cfg!(miri) { 100 } else { 10_000 };
+        let (tx, mut rx) = new::<usize>(8);
+        let tx = tx.into_sync();
+        let mut got = Vec::new();
+        thread::scope(|s| {
+            for p in 0..2 {
+                let tx = &tx;
+                s.spawn(move || {
+                    for i in 0..N {
+                        let mut el = p * N + i;
+                        while let Err(e) = tx.try_send(el) {
+                            el = e.into_inner();
+                            thread::yield_now();
+                        }
+                    }
+                });
+            }
+            while got.len() < 2 * N {
+                match rx.try_recv() {
+                    Ok(el) => got.push(el),
+                    Err(_) => thread::yield_now(),
+                }
+            }
+        });
+        got.sort_unstable();
+        assert!(got.into_iter().eq(0..2 * N));
+
+        // Clones like `Sender` does, whatever the element type.
+        struct NotClone(u8);
+        let (tx, mut rx) = new(2);
+        let tx = tx.into_sync();
+        let other = tx.clone();
+        drop(tx);
+        other.try_send(NotClone(1)).ok().unwrap();
+        assert_eq!(rx.try_recv().ok().unwrap().0, 1);
+    }
+}
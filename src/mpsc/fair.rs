@@ -0,0 +1,275 @@
+//! A multi-producer queue that serves producers in the order they arrive.
+//!
+//! Instead of racing for `head`, each send takes a ticket and then waits until
+//! its slot is free, so under contention no producer can lose the race
+//! forever. The price is that a send can't back out once it has a ticket:
+//! [`Sender::send`] waits for room instead of failing when the queue is full.
+
+use alloc::{
+    alloc::{Layout, alloc, handle_alloc_error},
+    boxed::Box,
+};
+use core::{ptr::NonNull, sync::atomic::Ordering};
+
+use super::{Meta, RX, SENDER};
+// The slots work as in `mpmc`, with a ticket as the position.
+use crate::{
+    cache::CachePadded,
+    mpmc::{Slot, free, written},
+};
+
+pub use crate::error::{SendError, TryRecvError};
+
+#[repr(C)]
+struct Shared<T> {
+    meta: Meta,
+    buffer: [Slot<T>],
+}
+
+impl<T> Shared<T> {
+    fn slot(&self, pos: usize) -> &Slot<T> {
+        &self.buffer[pos % self.buffer.len()]
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        // A sender that gave up on a ticket left its slot empty, so go by
+        // each slot's `seq` rather than assuming `tail..head` is full.
+        let head = *self.meta.head.0.get_mut();
+        let tail = *self.meta.tail.0.get_mut();
+        let len = self.buffer.len();
+        for i in 0..head.wrapping_sub(tail).min(len) {
+            let pos = tail.wrapping_add(i);
+            let slot = &mut self.buffer[pos % len];
+            if *slot.seq.get_mut() == written(pos) {
+                unsafe { slot.value.get_mut().assume_init_drop() };
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Sender<T> {
+    ptr: NonNull<Shared<T>>,
+}
+
+unsafe impl<T: Send> Send for Sender<T> {}
+
+impl<T> Sender<T> {
+    /// Sends `el`, waiting for room behind the sends that took a ticket
+    /// earlier.
+    ///
+    /// Fails if the receiver is gone, including while waiting.
+    pub fn send(&mut self, el: T) -> Result<(), SendError<T>> {
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.state.load(Ordering::Relaxed) & RX == 0 {
+            return Err(SendError(el));
+        }
+        let pos = shared.meta.head.fetch_add(1, Ordering::Relaxed);
+        let slot = shared.slot(pos);
+        while slot.seq.load(Ordering::Acquire) != free(pos) {
+            // Nobody will free the slot once the receiver is gone.
+            if shared.meta.state.load(Ordering::Relaxed) & RX == 0 {
+                return Err(SendError(el));
+            }
+            #[cfg(feature = "std")]
+            std::thread::yield_now();
+            #[cfg(not(feature = "std"))]
+            core::hint::spin_loop();
+        }
+        unsafe { (*slot.value.get()).write(el) };
+        slot.seq.store(written(pos), Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        unsafe { self.ptr.as_ref() }
+            .meta
+            .state
+            .fetch_add(SENDER, Ordering::Relaxed);
+        Sender { ptr: self.ptr }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.state.fetch_sub(SENDER, Ordering::AcqRel) == SENDER {
+            drop(unsafe { Box::from_non_null(self.ptr) });
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Receiver<T> {
+    ptr: NonNull<Shared<T>>,
+}
+
+unsafe impl<T: Send> Send for Receiver<T> {}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let shared = unsafe { self.ptr.as_ref() };
+        let tail = shared.meta.tail.load(Ordering::Relaxed);
+        let slot = shared.slot(tail);
+        let ready = written(tail);
+        if slot.seq.load(Ordering::Acquire) != ready {
+            if shared.meta.state.load(Ordering::Acquire) >= SENDER {
+                return Err(TryRecvError::Empty);
+            }
+            // The last sender may have written right before it dropped.
+            if slot.seq.load(Ordering::Acquire) != ready {
+                return Err(TryRecvError::Disconnected);
+            }
+        }
+        let el = unsafe { (*slot.value.get()).assume_init_read() };
+        // Hand the slot to the ticket one lap ahead.
+        slot.seq.store(
+            free(tail.wrapping_add(shared.buffer.len())),
+            Ordering::Release,
+        );
+        shared
+            .meta
+            .tail
+            .store(tail.wrapping_add(1), Ordering::Relaxed);
+        Ok(el)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.state.fetch_and(!RX, Ordering::AcqRel) == RX {
+            drop(unsafe { Box::from_non_null(self.ptr) });
+        }
+    }
+}
+
+/// Creates a queue that can hold up to `cap` elements and accepts any number
+/// of senders, served in the order they start sending.
+///
+/// # Panics
+///
+/// Panics if `cap` is zero.
+pub fn new<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(cap > 0, "queue capacity must be greater than zero");
+    let layout = Layout::new::<Meta>()
+        .extend(Layout::array::<Slot<T>>(cap).unwrap())
+        .unwrap();
+    let padded = layout.0.pad_to_align();
+    let ptr = NonNull::new(unsafe { alloc(padded) })
+        .unwrap_or_else(|| handle_alloc_error(padded))
+        .cast();
+    unsafe {
+        ptr.write(Meta {
+            state: (SENDER | RX).into(),
+            head: CachePadded(0.into()),
+            tail: CachePadded(0.into()),
+        });
+        let buffer = ptr.byte_add(layout.1).cast::<Slot<T>>();
+        for i in 0..cap {
+            buffer.add(i).write(Slot::new(i));
+        }
+    };
+    let thing = NonNull::from_raw_parts(ptr, cap);
+    (Sender { ptr: thing }, Receiver { ptr: thing })
+}
+
+#[cfg(test)]
+mod test {
+    use std::prelude::rust_2024::*;
+    use std::thread;
+
+    use crate::mpsc::fair::{SendError, new};
+
+    // Without `std`, waiting senders spin rather than yield, which can take
+    // forever when there are fewer cores than producers.
+    #[cfg(feature = "std")]
+    #[test]
+    fn producers() {
+        use core::sync::atomic::Ordering;
+
+        use crate::mpsc::fair::TryRecvError;
+
+        const PRODUCERS: usize = 8;
+        const CAP: usize = 4;
+        const N: usize = if cfg!(miri) { 50 } else { 20_000 };
+        let (mut tx, mut rx) = new::<usize>(CAP);
+        // Fill the queue, so every producer's first send has to wait.
+        for i in 0..CAP {
+            tx.send(usize::MAX - i).unwrap();
+        }
+        let handles: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let mut tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..N {
+                        tx.send(p * N + i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+        // Wait until each of them holds a ticket for its first send.
+        let head = &unsafe { rx.ptr.as_ref() }.meta.head;
+        while head.load(Ordering::Relaxed) < CAP + PRODUCERS {
+            thread::yield_now();
+        }
+
+        // Whatever the producers do from here on, a ticket is never
+        // overtaken: right behind what filled the queue comes one element
+        // from every producer, before any of them gets a second one in.
+        for i in 0..CAP {
+            assert_eq!(rx.try_recv(), Ok(usize::MAX - i));
+        }
+        let mut first = Vec::new();
+        while first.len() < PRODUCERS {
+            match rx.try_recv() {
+                Ok(el) => first.push(el),
+                Err(TryRecvError::Empty) => thread::yield_now(),
+                Err(TryRecvError::Disconnected) => unreachable!(),
+            }
+        }
+        first.sort_unstable();
+        assert_eq!(first, (0..PRODUCERS).map(|p| p * N).collect::<Vec<_>>());
+
+        let mut next = [1; PRODUCERS];
+        loop {
+            match rx.try_recv() {
+                Ok(el) => {
+                    // Each producer's items arrive in the order it sent them.
+                    let (p, i) = (el / N, el % N);
+                    assert_eq!(next[p], i);
+                    next[p] += 1;
+                }
+                Err(TryRecvError::Empty) => thread::yield_now(),
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        assert_eq!(next, [N; PRODUCERS]);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        // One ticket per send, none of them retried.
+        assert_eq!(head.load(Ordering::Relaxed), CAP + PRODUCERS * N);
+    }
+
+    #[test]
+    fn disconnect() {
+        let (mut tx, rx) = new(1);
+        tx.send(String::from("a")).unwrap();
+        let mut other = tx.clone();
+        // Waits behind "a" until the receiver goes away, leaving its ticket
+        // unused.
+        let handle = thread::spawn(move || other.send(String::from("b")));
+        drop(rx);
+        assert_eq!(handle.join().unwrap(), Err(SendError(String::from("b"))));
+        assert_eq!(
+            tx.send(String::from("c")),
+            Err(SendError(String::from("c")))
+        );
+    }
+}
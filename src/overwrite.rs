@@ -0,0 +1,281 @@
+use alloc::{
+    alloc::{Layout, alloc, handle_alloc_error},
+    boxed::Box,
+};
+use core::{
+    cell::UnsafeCell,
+    hint,
+    mem::MaybeUninit,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::cache::CachePadded;
+
+pub use crate::error::TryRecvError;
+
+// `reading` value while the receiver isn't moving anything out.
+const IDLE: usize = 0;
+
+struct Meta {
+    // Allocation info
+    tx_dropped: AtomicBool,
+    refs: AtomicUsize,
+
+    // Queue info. Unlike in `spsc`, both sides move `tail`: the receiver to
+    // take an element, the sender to evict one from a full queue. Whoever wins
+    // the CAS owns the element.
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+    // One past the index of the slot the receiver is claiming or moving out of,
+    // or `IDLE`. The sender waits for it to clear before writing that slot.
+    reading: CachePadded<AtomicUsize>,
+}
+
+#[repr(C)]
+struct Shared<T> {
+    meta: Meta,
+    buffer: [UnsafeCell<MaybeUninit<T>>],
+}
+
+impl<T> Shared<T> {
+    fn index(&self, pos: usize) -> usize {
+        pos % self.buffer.len()
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let head = *self.meta.head.0.get_mut();
+        let mut tail = *self.meta.tail.0.get_mut();
+        while tail != head {
+            let index = self.index(tail);
+            unsafe { self.buffer[index].get_mut().assume_init_drop() };
+            tail = tail.wrapping_add(1);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Sender<T> {
+    ptr: NonNull<Shared<T>>,
+}
+
+unsafe impl<T: Send> Send for Sender<T> {}
+
+impl<T> Sender<T> {
+    /// Sends `el`, evicting the oldest element if the queue is full.
+    ///
+    /// Returns the evicted element, if any. The receiver may take the oldest
+    /// element at the same moment, in which case nothing is evicted.
+    ///
+    /// Sending never fails, even once the receiver is gone; whatever is left
+    /// then is dropped along with the queue.
+    pub fn force_send(&mut self, el: T) -> Option<T> {
+        let shared = unsafe { self.ptr.as_ref() };
+        let head = shared.meta.head.load(Ordering::Relaxed);
+        let tail = shared.meta.tail.load(Ordering::SeqCst);
+        let mut evicted = None;
+        if head.wrapping_sub(tail) == shared.buffer.len()
+            && shared
+                .meta
+                .tail
+                .compare_exchange(
+                    tail,
+                    tail.wrapping_add(1),
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .is_ok()
+        {
+            evicted =
+                Some(unsafe { (*shared.buffer[shared.index(tail)].get()).assume_init_read() });
+        }
+
+        // Whoever claimed the previous occupant of this slot, it is no longer
+        // live. The receiver may still be moving it out, though.
+        let index = shared.index(head);
+        while shared.meta.reading.load(Ordering::SeqCst) == index + 1 {
+            hint::spin_loop();
+        }
+        unsafe { (*shared.buffer[index].get()).write(el) };
+        shared
+            .meta
+            .head
+            .store(head.wrapping_add(1), Ordering::Release);
+        evicted
+    }
+
+    /// Sends `el` like [`force_send`](Self::force_send), handing the evicted
+    /// element, if any, to `on_evict`.
+    ///
+    /// `on_evict` runs once `el` is already in the queue, so a panicking
+    /// callback doesn't lose it.
+    pub fn send_evicting(&mut self, el: T, on_evict: impl FnOnce(T)) {
+        if let Some(evicted) = self.force_send(el) {
+            on_evict(evicted);
+        }
+    }
+
+    /// Returns the number of elements the queue can hold.
+    pub fn capacity(&self) -> usize {
+        unsafe { self.ptr.as_ref() }.buffer.len()
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let shared = unsafe { self.ptr.as_ref() };
+        shared.meta.tx_dropped.store(true, Ordering::Release);
+        if shared.meta.refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+            drop(unsafe { Box::from_non_null(self.ptr) });
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Receiver<T> {
+    ptr: NonNull<Shared<T>>,
+}
+
+unsafe impl<T: Send> Send for Receiver<T> {}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let shared = unsafe { self.ptr.as_ref() };
+        let mut tail = shared.meta.tail.load(Ordering::SeqCst);
+        loop {
+            let mut head = shared.meta.head.load(Ordering::Acquire);
+            if tail == head {
+                shared.meta.reading.store(IDLE, Ordering::Release);
+                if !shared.meta.tx_dropped.load(Ordering::Acquire) {
+                    return Err(TryRecvError::Empty);
+                }
+                head = shared.meta.head.load(Ordering::Acquire);
+                if tail == head {
+                    return Err(TryRecvError::Disconnected);
+                }
+            }
+            // Announce the slot before claiming it, so a sender that sees the
+            // claim also sees that we may be reading.
+            let index = shared.index(tail);
+            shared.meta.reading.store(index + 1, Ordering::SeqCst);
+            match shared.meta.tail.compare_exchange(
+                tail,
+                tail.wrapping_add(1),
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => {
+                    let el = unsafe { (*shared.buffer[index].get()).assume_init_read() };
+                    shared.meta.reading.store(IDLE, Ordering::Release);
+                    return Ok(el);
+                }
+                // The sender evicted this one; try the next.
+                Err(current) => tail = current,
+            }
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+            drop(unsafe { Box::from_non_null(self.ptr) });
+        }
+    }
+}
+
+/// Creates a queue that holds the latest `cap` elements, evicting older ones
+/// instead of rejecting new ones.
+///
+/// # Panics
+///
+/// Panics if `cap` is zero.
+pub fn new<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(cap > 0, "queue capacity must be greater than zero");
+    let layout = Layout::new::<Meta>()
+        .extend(Layout::array::<T>(cap).unwrap())
+        .unwrap();
+    let padded = layout.0.pad_to_align();
+    let ptr = NonNull::new(unsafe { alloc(padded) })
+        .unwrap_or_else(|| handle_alloc_error(padded))
+        .cast();
+    unsafe {
+        ptr.write(Meta {
+            tx_dropped: false.into(),
+            refs: 2.into(),
+            head: CachePadded(0.into()),
+            tail: CachePadded(0.into()),
+            reading: CachePadded(IDLE.into()),
+        });
+    };
+    let thing = NonNull::from_raw_parts(ptr, cap);
+    (Sender { ptr: thing }, Receiver { ptr: thing })
+}
+
+#[cfg(test)]
+mod test {
+    use std::prelude::rust_2024::*;
+    use std::thread;
+
+    use crate::overwrite::{TryRecvError, new};
+
+    #[test]
+    fn latest_wins() {
+        let (mut tx, mut rx) = new::<u32>(3);
+        for i in 0..3 {
+            assert_eq!(tx.force_send(i), None);
+        }
+        for i in 3..10 {
+            assert_eq!(tx.force_send(i), Some(i - 3));
+        }
+        assert_eq!(rx.try_recv(), Ok(7));
+        assert_eq!(rx.try_recv(), Ok(8));
+        assert_eq!(rx.try_recv(), Ok(9));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn send_evicting() {
+        let (mut tx, mut rx) = new(2);
+        let mut evicted = Vec::new();
+        for i in 0..5 {
+            tx.send_evicting(i, |el| evicted.push(el));
+        }
+        assert_eq!(evicted, [0, 1, 2]);
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Ok(4));
+    }
+
+    #[test]
+    fn concurrent() {
+        const N: usize = if cfg!(miri) { 1_000 } else { 200_000 };
+        let (mut tx, mut rx) = new::<Box<usize>>(4);
+        let producer = thread::spawn(move || {
+            let mut evicted = 0;
+            for i in 0..N {
+                evicted += tx.force_send(Box::new(i)).is_some() as usize;
+            }
+            evicted
+        });
+        let mut received = 0;
+        let mut last = None;
+        loop {
+            match rx.try_recv() {
+                Ok(el) => {
+                    assert!(last.is_none_or(|last| last < *el));
+                    last = Some(*el);
+                    received += 1;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        // Every element was either received or evicted, never both.
+        assert_eq!(received + producer.join().unwrap(), N);
+    }
+}
@@ -0,0 +1,136 @@
+use alloc::vec::Vec;
+use core::array;
+
+use crate::spsc;
+
+pub use crate::error::{TryRecvError, TrySendError};
+
+/// The number of priority levels, so distinct priorities are `0..LEVELS`.
+pub const LEVELS: usize = 4;
+
+// One plain bounded queue per level. The receiver looks at the most urgent
+// level first, so elements come out by priority and in order within a level,
+// but there is no order between elements of different levels beyond that.
+
+/// The sending half of a [`priority`](new) queue.
+#[derive(Debug)]
+pub struct Sender<T> {
+    levels: [spsc::Sender<T>; LEVELS],
+}
+
+impl<T> Sender<T> {
+    /// Sends `el` at priority `prio`, where higher is more urgent.
+    ///
+    /// Priorities from [`LEVELS`] up all go to the most urgent level,
+    /// `LEVELS - 1`.
+    ///
+    /// Each level fills up on its own, so this can report a full queue while
+    /// other levels still have room.
+    pub fn try_send(&mut self, el: T, prio: u8) -> Result<(), TrySendError<T>> {
+        let level = usize::from(prio).min(LEVELS - 1);
+        self.levels[level].try_send(el)
+    }
+
+    /// Returns the number of elements each level can hold.
+    pub fn capacity(&self) -> usize {
+        self.levels[0].capacity()
+    }
+}
+
+/// The receiving half of a [`priority`](new) queue.
+#[derive(Debug)]
+pub struct Receiver<T> {
+    levels: [spsc::Receiver<T>; LEVELS],
+}
+
+impl<T> Receiver<T> {
+    /// Receives the oldest element of the most urgent level that has one.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut err = TryRecvError::Disconnected;
+        for level in self.levels.iter_mut().rev() {
+            match level.try_recv() {
+                Ok(el) => return Ok(el),
+                // A level already looked at may have been sent to since, so
+                // only report the end once every level said so.
+                Err(TryRecvError::Empty) => err = TryRecvError::Empty,
+                Err(TryRecvError::Disconnected) => {}
+            }
+        }
+        Err(err)
+    }
+
+    /// Returns the number of elements queued across all levels.
+    pub fn len(&self) -> usize {
+        self.levels.iter().map(spsc::Receiver::len).sum()
+    }
+
+    /// Returns `true` if no level has anything queued.
+    pub fn is_empty(&self) -> bool {
+        self.levels.iter().all(spsc::Receiver::is_empty)
+    }
+}
+
+/// Creates a queue that hands out elements by priority, with room for `cap`
+/// elements at each of the [`LEVELS`] levels.
+///
+/// # Panics
+///
+/// Panics if `cap` is zero.
+pub fn new<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    let mut rxs = Vec::with_capacity(LEVELS);
+    let txs = array::from_fn(|_| {
+        let (tx, rx) = spsc::new(cap);
+        rxs.push(rx);
+        tx
+    });
+    let mut rxs = rxs.into_iter();
+    let rxs = array::from_fn(|_| rxs.next().unwrap());
+    (Sender { levels: txs }, Receiver { levels: rxs })
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use std::prelude::rust_2024::*;
+
+    use crate::priority::{TryRecvError, TrySendError, new};
+
+    #[test]
+    fn order() {
+        let (mut tx, mut rx) = new(4);
+        for (el, prio) in [("a", 0), ("b", 2), ("c", 0), ("d", 3), ("e", 2)] {
+            tx.try_send(el, prio).unwrap();
+        }
+        assert_eq!(rx.len(), 5);
+        // By priority, then in order sent within a level.
+        let got: Vec<_> = core::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(got, ["d", "b", "e", "a", "c"]);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        tx.try_send("f", 1).unwrap();
+        drop(tx);
+        assert_eq!(rx.try_recv(), Ok("f"));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn levels_fill_separately() {
+        let (mut tx, rx) = new(1);
+        tx.try_send(1, 0).unwrap();
+        assert_eq!(tx.try_send(2, 0), Err(TrySendError::Full(2)));
+        tx.try_send(2, 1).unwrap();
+        drop(rx);
+        assert_eq!(tx.try_send(3, 3), Err(TrySendError::Disconnected(3)));
+    }
+
+    #[test]
+    fn prio_out_of_range() {
+        let (mut tx, mut rx) = new(2);
+        tx.try_send("a", 3).unwrap();
+        tx.try_send("b", u8::MAX).unwrap();
+        // Both went to the top level, which is now full.
+        assert_eq!(tx.try_send("c", 4), Err(TrySendError::Full("c")));
+        tx.try_send("d", 2).unwrap();
+        let got: Vec<_> = core::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(got, ["a", "b", "d"]);
+    }
+}
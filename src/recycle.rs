@@ -0,0 +1,167 @@
+use core::{
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+};
+
+use crate::spsc;
+
+pub use crate::error::{TryRecvError, TrySendError};
+
+// Two plain bounded queues running in opposite directions: one carries the
+// elements, the other hands storage the receiver is done with back to the
+// sender. Neither side ever waits on the second one, so a full or abandoned
+// spare queue just means the storage gets dropped instead.
+
+/// The sending half of a [`recycling`](new) queue.
+#[derive(Debug)]
+pub struct Sender<T> {
+    tx: spsc::Sender<T>,
+    spare: spsc::Receiver<T>,
+}
+
+impl<T> Sender<T> {
+    pub fn try_send(&mut self, el: T) -> Result<(), TrySendError<T>> {
+        self.tx.try_send(el)
+    }
+
+    /// Takes back an element the receiver is done with, if there is one.
+    ///
+    /// It comes back exactly as the receiver left it, so whatever it held is
+    /// still there unless the receiver cleared it.
+    pub fn take_spare(&mut self) -> Option<T> {
+        self.spare.try_recv().ok()
+    }
+}
+
+/// The receiving half of a [`recycling`](new) queue.
+#[derive(Debug)]
+pub struct Receiver<T> {
+    rx: spsc::Receiver<T>,
+    spare: spsc::Sender<T>,
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.rx.try_recv()
+    }
+
+    /// Receives an element that goes back to the sender once it is dropped.
+    ///
+    /// Returns `None` if the queue is empty, whether or not the sender is
+    /// still around.
+    pub fn recv_recycle(&mut self) -> Option<Recycled<'_, T>> {
+        let el = self.rx.try_recv().ok()?;
+        Some(Recycled {
+            el: ManuallyDrop::new(el),
+            spare: &mut self.spare,
+        })
+    }
+}
+
+/// An element handed out by [`Receiver::recv_recycle`].
+///
+/// It derefs to the element. Dropping it hands the element back to the
+/// sender, to be picked up with [`Sender::take_spare`], or drops it for real
+/// if the sender is gone or already has a queue full of spares.
+#[derive(Debug)]
+pub struct Recycled<'a, T> {
+    el: ManuallyDrop<T>,
+    spare: &'a mut spsc::Sender<T>,
+}
+
+impl<T> Recycled<'_, T> {
+    /// Keeps the element instead of handing it back.
+    pub fn into_inner(self) -> T {
+        let mut this = ManuallyDrop::new(self);
+        unsafe { ManuallyDrop::take(&mut this.el) }
+    }
+}
+
+impl<T> Deref for Recycled<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.el
+    }
+}
+
+impl<T> DerefMut for Recycled<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.el
+    }
+}
+
+impl<T> Drop for Recycled<'_, T> {
+    fn drop(&mut self) {
+        let el = unsafe { ManuallyDrop::take(&mut self.el) };
+        // Dropped right here if it doesn't fit.
+        let _ = self.spare.try_send(el);
+    }
+}
+
+/// Creates a queue that can hold up to `cap` elements and hands received
+/// elements back to the sender for reuse.
+///
+/// Meant for elements that own reusable storage, like `Vec<u8>` buffers,
+/// so a busy pipeline doesn't allocate a fresh one for every message. Up to
+/// `cap` spares are kept around for the sender.
+///
+/// # Panics
+///
+/// Panics if `cap` is zero.
+pub fn new<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = spsc::new(cap);
+    let (spare_tx, spare_rx) = spsc::new(cap);
+    (
+        Sender {
+            tx,
+            spare: spare_rx,
+        },
+        Receiver {
+            rx,
+            spare: spare_tx,
+        },
+    )
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use std::prelude::rust_2024::*;
+
+    use crate::recycle::{TryRecvError, new};
+
+    #[test]
+    fn reuse() {
+        let (mut tx, mut rx) = new::<Vec<u8>>(2);
+        assert_eq!(tx.take_spare(), None);
+        tx.try_send(vec![1, 2, 3]).unwrap();
+
+        let mut buf = rx.recv_recycle().unwrap();
+        assert_eq!(*buf, [1, 2, 3]);
+        let ptr = buf.as_ptr();
+        buf.clear();
+        drop(buf);
+
+        // The sender gets the same allocation back.
+        let mut buf = tx.take_spare().unwrap();
+        assert_eq!((buf.as_ptr(), buf.len()), (ptr, 0));
+        buf.extend_from_slice(&[4, 5]);
+        tx.try_send(buf).unwrap();
+        let buf = rx.recv_recycle().unwrap();
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(buf.into_inner(), [4, 5]);
+        // Kept elements don't come back.
+        assert_eq!(tx.take_spare(), None);
+        assert!(rx.recv_recycle().is_none());
+    }
+
+    #[test]
+    fn sender_gone() {
+        let (mut tx, mut rx) = new(1);
+        tx.try_send(String::from("a")).unwrap();
+        drop(tx);
+        // Handing it back to a dropped sender just drops it.
+        drop(rx.recv_recycle().unwrap());
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+}
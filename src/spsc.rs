@@ -1,133 +1,6146 @@
+use alloc::{
+    alloc::{AllocError, Allocator, Global, Layout, LayoutError, handle_alloc_error},
+    vec::Vec,
+};
+use core::{
+    cell::{Cell, UnsafeCell},
+    fmt,
+    marker::PhantomData,
+    mem::{self, ManuallyDrop, MaybeUninit},
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+};
+
+#[cfg(all(feature = "metrics", feature = "std"))]
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use core::iter::FusedIterator;
+#[cfg(feature = "std")]
 use std::{
-    alloc::{Layout, alloc},
-    mem::MaybeUninit,
-    ptr::NonNull,
-    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    io,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "async")]
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "async")]
+use futures_core::Stream;
+#[cfg(feature = "async")]
+use futures_sink::Sink;
+
+#[cfg(feature = "metrics")]
+use crate::sync::AtomicBool;
+#[cfg(feature = "heartbeat")]
+use crate::sync::AtomicU64;
+#[cfg(feature = "std")]
+use crate::waker;
+use crate::{
+    cache::CachePadded,
+    sync::{AtomicPtr, AtomicUsize, Ordering, spin_loop},
+    waker::AtomicWaker,
 };
 
+pub use crate::error::{
+    RecvError, RecvTimeoutError, SendError, SendTimeoutError, TryRecvError, TrySendError,
+};
+
+pub mod pow2;
+
 struct Meta {
-    // Allocation info
-    tx_dropped: AtomicBool,
-    rx_dropped: AtomicBool,
+    // Allocation info. See the bits below; whoever clears the last `HELD`
+    // bit frees the allocation.
+    state: AtomicUsize,
+
+    // Queue info. The producer writes `head` while the consumer writes `tail`,
+    // so each gets its own cache line. `cap` duplicates the length of the
+    // buffer so a thin pointer is enough to rebuild a handle.
+    //
+    // The positions are only ever compared through `distance`, which is at
+    // most the capacity, so they can wrap any number of times without
+    // becoming ambiguous, whatever the width of `usize`.
+    //
+    // Each side loads its own position `Relaxed`, since nobody else writes
+    // it, loads the other's with `Acquire` and publishes its own with
+    // `Release`. That pairing is what makes a slot's contents visible before
+    // its position is, so there is nothing weaker left to choose.
+    cap: usize,
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+    // How far the consumer says it has processed what it received. Only
+    // ever at or behind `tail`.
+    acked: AtomicUsize,
+    // `capacity - 1` when the capacity is a power of two, which turns the
+    // index computation into a mask instead of a division.
+    mask: Option<usize>,
+    // Positions count up to this multiple of the capacity and start over at
+    // zero, so `pos % capacity` has no jump where they wrap. Zero when the
+    // capacity divides `usize::MAX + 1` and positions can just overflow.
+    wrap: usize,
 
-    // Queue info
-    head: AtomicUsize,
-    tail: AtomicUsize,
+    // Resizing info. The buffer `Receiver::shrink_to` left for the sender,
+    // set at most once. The sender only moves over when the queue is empty
+    // and never comes back, so once `MOVED` is set the receiver has nothing
+    // left to take here and follows. Until then the new buffer's `TX` bit is
+    // held for the sender, and freeing this buffer frees that one too.
+    next: AtomicPtr<()>,
+
+    // Blocking info
+    tx_waker: AtomicWaker,
+    rx_waker: AtomicWaker,
+
+    // Statistics. The sender writes the first three, the receiver the last.
+    #[cfg(feature = "metrics")]
+    total_sent: AtomicUsize,
+    #[cfg(feature = "metrics")]
+    send_full_rejections: AtomicUsize,
+    #[cfg(feature = "metrics")]
+    last_send_blocked: AtomicBool,
+    #[cfg(feature = "metrics")]
+    total_received: AtomicUsize,
+
+    // Nanoseconds after `epoch` at which an element was last sent or
+    // received.
+    #[cfg(feature = "heartbeat")]
+    epoch: Instant,
+    #[cfg(feature = "heartbeat")]
+    last_active: AtomicU64,
 }
 
+// Bits of `Meta::state`. A side that is gone but still waking the other one
+// swaps its present bit for its waking bit, so it looks gone while keeping
+// the allocation alive until it is done.
+const TX: usize = 1 << 0;
+const RX: usize = 1 << 1;
+const TX_WAKING: usize = 1 << 2;
+const RX_WAKING: usize = 1 << 3;
+const HELD: usize = TX | RX | TX_WAKING | RX_WAKING;
+// Set on top of clearing `TX` when the sender ended the stream on purpose.
+const CLOSED: usize = 1 << 4;
+// The two steps of the shutdown handshake: the sender asks the receiver to
+// wrap up, and the receiver answers once it has.
+const DRAINING: usize = 1 << 5;
+const SHUTDOWN_ACKED: usize = 1 << 6;
+// Set by `Sink::poll_close`, and turned into `CLOSED` once the sender drops.
+const CLOSING: usize = 1 << 7;
+// Set by the receiver once it left a buffer in `next`, then by the sender
+// as it starts moving over to that buffer and once it is done.
+const RESIZING: usize = 1 << 8;
+const MOVING: usize = 1 << 9;
+const MOVED: usize = 1 << 10;
+// Set for queues whose handles carry the capacity in their type, which
+// therefore can't be resized.
+const FIXED: usize = 1 << 11;
+
 #[repr(C)]
-struct Shared<T> {
+struct Shared<T, A: Allocator> {
     meta: Meta,
-    buffer: [MaybeUninit<T>],
+    alloc: ManuallyDrop<A>,
+    // Each side only ever holds a shared reference to `Shared`. The slots it
+    // owns at the moment are written and read through the cells.
+    buffer: [UnsafeCell<MaybeUninit<T>>],
 }
 
-#[derive(Debug)]
-pub struct Sender<T> {
-    ptr: NonNull<Shared<T>>,
+impl<T, A: Allocator> Shared<T, A> {
+    /// Drops everything still queued and hands the memory back to the
+    /// allocator it came from.
+    ///
+    /// # Safety
+    ///
+    /// Both endpoints must be done with `ptr`.
+    unsafe fn free(ptr: NonNull<Self>) {
+        unsafe {
+            let meta = &ptr.as_ref().meta;
+            // A buffer left for the sender that it never moved over to.
+            let pending = NonNull::new(meta.next.load(Ordering::Relaxed))
+                .filter(|_| meta.state.load(Ordering::Relaxed) & MOVED == 0);
+            let layout = Layout::for_value(ptr.as_ref());
+            let alloc = ManuallyDrop::take(&mut (*ptr.as_ptr()).alloc);
+            ptr.drop_in_place();
+            alloc.deallocate(ptr.cast(), layout);
+            if let Some(pending) = pending {
+                Self::free(Self::from_thin(pending));
+            }
+        }
+    }
+
+    /// Rebuilds the pointer to a queue from its address, reading the
+    /// capacity from the header.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a live queue of `T` allocated with `A`.
+    unsafe fn from_thin(ptr: NonNull<()>) -> NonNull<Self> {
+        let cap = unsafe { ptr.cast::<Meta>().as_ref() }.cap;
+        NonNull::from_raw_parts(ptr, cap)
+    }
+
+    /// Returns the buffer the sender moved over to, waiting for it to get
+    /// there if it is only on its way.
+    ///
+    /// # Safety
+    ///
+    /// `MOVING` must be set, and the receiver still hold the buffer it leads
+    /// to.
+    unsafe fn moved_to(&self) -> NonNull<Self> {
+        // The two bits are a few stores apart on the sender's side.
+        while self.meta.state.load(Ordering::Acquire) & MOVED == 0 {
+            spin_loop();
+        }
+        let next = self.meta.next.load(Ordering::Relaxed);
+        unsafe { Self::from_thin(NonNull::new_unchecked(next)) }
+    }
+
+    fn index(&self, pos: usize) -> usize {
+        match self.meta.mask {
+            Some(mask) => pos & mask,
+            None => pos % self.buffer.len(),
+        }
+    }
+
+    /// Returns the slot for `pos`.
+    fn slot(&self, pos: usize) -> *mut MaybeUninit<T> {
+        self.buffer[self.index(pos)].get()
+    }
+
+    /// Returns the first slot, from which bulk copies are offset.
+    fn slots(&self) -> *mut T {
+        UnsafeCell::raw_get(self.buffer.as_ptr()).cast()
+    }
+
+    /// Returns the position `n` slots after `pos`.
+    fn advance(&self, pos: usize, n: usize) -> usize {
+        match self.meta.wrap {
+            0 => pos.wrapping_add(n),
+            wrap if pos >= wrap - n => pos - (wrap - n),
+            _ => pos + n,
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    fn stats(&self) -> Stats {
+        Stats {
+            total_sent: self.meta.total_sent.load(Ordering::Relaxed),
+            total_received: self.meta.total_received.load(Ordering::Relaxed),
+            send_full_rejections: self.meta.send_full_rejections.load(Ordering::Relaxed),
+        }
+    }
+
+    fn is_present(&self, side: usize, order: Ordering) -> bool {
+        self.meta.state.load(order) & side != 0
+    }
+
+    /// Lets go of the handle whose present bit is `me`, waking the other
+    /// side through `waker` if it is still around. Returns `true` if the
+    /// caller has to free the allocation.
+    fn release(&self, me: usize, waking: usize, other: usize, waker: &AtomicWaker) -> bool {
+        let state = &self.meta.state;
+        let mut held = me;
+        // The other side can't come back once it's gone, so a stale read only
+        // costs a wakeup nobody waits for.
+        if state.load(Ordering::Relaxed) & other != 0 {
+            state.fetch_xor(me | waking, Ordering::AcqRel);
+            // A side blocked waiting on us has to notice we are gone.
+            waker.wake();
+            held = waking;
+        }
+        state.fetch_and(!held, Ordering::AcqRel) & HELD == held
+    }
+
+    #[cfg(feature = "heartbeat")]
+    fn beat(&self) {
+        let nanos = self.meta.epoch.elapsed().as_nanos() as u64;
+        self.meta.last_active.store(nanos, Ordering::Relaxed);
+    }
+
+    fn positions(&self) -> (usize, usize) {
+        let head = self.meta.head.load(Ordering::Relaxed);
+        let tail = self.meta.tail.load(Ordering::Relaxed);
+        (head, tail)
+    }
+
+    /// Returns how many slots `head` is ahead of `tail`.
+    fn distance(&self, head: usize, tail: usize) -> usize {
+        match self.meta.wrap {
+            0 => head.wrapping_sub(tail),
+            _ if head >= tail => head - tail,
+            wrap => head + (wrap - tail),
+        }
+    }
+
+    /// Returns how many elements sit between `tail` and `head`, or `None` if
+    /// that is more than fit, which means the positions are corrupt.
+    fn checked_len(&self, head: usize, tail: usize) -> Option<usize> {
+        let len = self.distance(head, tail);
+        (len <= self.buffer.len()).then_some(len)
+    }
+
+    /// Panics if the queue's state doesn't add up, as seen from the side whose
+    /// present bit is `me`.
+    #[cfg(any(test, feature = "debug-checks"))]
+    fn assert_invariants(&self, me: usize) {
+        let state = self.meta.state.load(Ordering::Acquire);
+        assert!(
+            state & me != 0,
+            "live handle not marked present: {state:#b}"
+        );
+        // A side swaps its present bit for its waking bit, never holds both.
+        for (present, waking) in [(TX, TX_WAKING), (RX, RX_WAKING)] {
+            assert!(
+                state & present == 0 || state & waking == 0,
+                "side both present and waking: {state:#b}"
+            );
+        }
+        let (head, tail) = self.positions();
+        if self.meta.wrap != 0 {
+            assert!(
+                head < self.meta.wrap && tail < self.meta.wrap,
+                "positions past the wrap point: head {head}, tail {tail}"
+            );
+        }
+        assert!(
+            self.checked_len(head, tail).is_some(),
+            "more queued than fit: head {head}, tail {tail}"
+        );
+    }
+
+    /// Like [`checked_len`](Self::checked_len), but only checked in debug
+    /// builds.
+    fn len(&self, head: usize, tail: usize) -> usize {
+        let len = self.distance(head, tail);
+        debug_assert!(
+            len <= self.buffer.len(),
+            "queue positions are inconsistent: head {head}, tail {tail}"
+        );
+        len
+    }
+}
+
+impl<T, A: Allocator> Drop for Shared<T, A> {
+    fn drop(&mut self) {
+        // Only reached once both endpoints are gone, so every slot between
+        // `tail` and `head` still holds an initialized value nobody received.
+        // `Sender::publish` never moves `head` past a slot that wasn't
+        // written, whatever happened to reservations along the way.
+        let head = self.meta.head.load(Ordering::Relaxed);
+        let mut tail = self.meta.tail.load(Ordering::Relaxed);
+        while tail != head {
+            let index = self.index(tail);
+            unsafe { self.buffer[index].get_mut().assume_init_drop() };
+            tail = self.advance(tail, 1);
+        }
+    }
+}
+
+/// What became of the element passed to [`Sender::try_send_keep`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendStatus {
+    /// The element was taken and queued.
+    Sent,
+    /// The queue was full, so the element was left where it was.
+    Full,
+    /// The receiver is gone, so the element was left where it was.
+    Disconnected,
 }
 
-unsafe impl<T> Send for Sender<T> {}
+/// Counters returned by [`Sender::stats`] and [`Receiver::stats`].
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Stats {
+    /// Elements sent through `try_send` and the methods built on it.
+    pub total_sent: usize,
+    /// Elements received through `try_recv` and the methods built on it.
+    pub total_received: usize,
+    /// `try_send` calls that found the queue full.
+    pub send_full_rejections: usize,
+}
+
+#[cfg(all(feature = "metrics", feature = "std"))]
+const RECV_LOG: usize = 16;
+
+/// When the last few receives happened and how many elements each took, for
+/// [`Receiver::throughput`].
+#[cfg(all(feature = "metrics", feature = "std"))]
+struct RecvLog {
+    // A ring, with the oldest entry at `next` once `len` reaches its size.
+    entries: [(Instant, usize); RECV_LOG],
+    next: usize,
+    len: usize,
+}
+
+#[cfg(all(feature = "metrics", feature = "std"))]
+impl RecvLog {
+    fn new() -> Self {
+        RecvLog {
+            entries: [(Instant::now(), 0); RECV_LOG],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    fn record(&mut self, n: usize) {
+        self.entries[self.next] = (Instant::now(), n);
+        self.next = (self.next + 1) % RECV_LOG;
+        self.len = (self.len + 1).min(RECV_LOG);
+    }
+
+    fn rate(&self, window: Duration) -> f64 {
+        let now = Instant::now();
+        let mut in_window = (1..=self.len)
+            .map(|i| self.entries[(self.next + RECV_LOG - i) % RECV_LOG])
+            .take_while(|&(at, _)| now.duration_since(at) <= window);
+        // The oldest receive only marks where the measurement starts; what it
+        // took arrived before that.
+        let Some(mut start) = in_window.next() else {
+            return 0.0;
+        };
+        let mut count = 0;
+        for entry in in_window {
+            count += start.1;
+            start = entry;
+        }
+        match now.duration_since(start.0).as_secs_f64() {
+            0.0 => 0.0,
+            secs => count as f64 / secs,
+        }
+    }
+}
+
+pub struct Sender<T, A: Allocator = Global> {
+    ptr: NonNull<Shared<T, A>>,
+}
 
-impl<T> Sender<T> {
-    pub fn try_send(&mut self, el: T) -> Option<T> {
-        let shared = &mut unsafe { self.ptr.as_mut() };
+// Deliberately not `Sync`. Sending relies on being the only one moving
+// `head`, and staying `!Sync` keeps that true should a `&self` method ever
+// touch it. `mpsc::SyncSender` is the one to share by reference.
+unsafe impl<T: Send, A: Allocator + Send> Send for Sender<T, A> {}
+
+/// Spins this many times between writing a slot and publishing it, so tests
+/// can give the receiver a wider window to look at a slot too early.
+#[cfg(all(test, not(loom)))]
+static PUBLISH_DELAY: core::sync::atomic::AtomicU32 = core::sync::atomic::AtomicU32::new(0);
+
+impl<T, A: Allocator> fmt::Debug for Sender<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Only the counters. Reading the elements would race the receiver.
+        f.debug_struct("Sender")
+            .field("capacity", &self.capacity())
+            .field("len", &self.len())
+            .field("connected", &self.is_connected())
+            .finish()
+    }
+}
+
+impl<T, A: Allocator> Sender<T, A> {
+    pub fn try_send(&mut self, el: T) -> Result<(), TrySendError<T>> {
+        self.try_send_len(el).map(drop)
+    }
+
+    /// The old `try_send`, handing the element back as `Some` whether the
+    /// queue was full or the receiver gone.
+    #[deprecated(note = "use `try_send`, which tells a full queue from a disconnected one")]
+    pub fn try_send_opt(&mut self, el: T) -> Option<T> {
+        self.try_send(el).err().map(TrySendError::into_inner)
+    }
+
+    /// Sends an element like [`try_send`](Self::try_send) and returns how
+    /// many elements are queued right after it, counting it.
+    ///
+    /// This comes from the positions the send looks at anyway, so a producer
+    /// can back off as the queue fills without another look at `tail`. The
+    /// receiver may have taken some of them by the time this returns.
+    pub fn try_send_len(&mut self, el: T) -> Result<usize, TrySendError<T>> {
+        self.resize_if_asked();
+        let shared = unsafe { self.ptr.as_ref() };
+        // Nothing will ever take `el` out once the receiver is gone, even if
+        // there is room for it.
+        if !shared.is_present(RX, Ordering::Relaxed) {
+            return Err(TrySendError::Disconnected(el));
+        }
         let head = shared.meta.head.load(Ordering::Relaxed);
         let tail = shared.meta.tail.load(Ordering::Acquire);
-        if head.wrapping_sub(tail) == shared.buffer.len() {
-            Some(el)
+        let len = shared.distance(head, tail);
+        if len == shared.buffer.len() {
+            self.note_full();
+            Err(TrySendError::Full(el))
         } else {
-            shared.buffer[head % shared.buffer.len()].write(el);
+            unsafe {
+                (*shared.slot(head)).write(el);
+                self.publish(head, 1);
+            }
+            Ok(len + 1)
+        }
+    }
+
+    /// Sends the element in `el` if there is room, taking it out only once
+    /// it is certain to be queued.
+    ///
+    /// Unlike [`try_send`](Self::try_send) nothing is moved in and back out
+    /// of the call when the queue is full, which adds up for big elements in
+    /// a loop that keeps retrying. On [`Full`](SendStatus::Full) and
+    /// [`Disconnected`](SendStatus::Disconnected) `el` is left untouched.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `el` is `None`.
+    pub fn try_send_keep(&mut self, el: &mut Option<T>) -> SendStatus {
+        assert!(el.is_some(), "no element to send");
+        self.resize_if_asked();
+        let shared = unsafe { self.ptr.as_ref() };
+        if !shared.is_present(RX, Ordering::Relaxed) {
+            return SendStatus::Disconnected;
+        }
+        let head = shared.meta.head.load(Ordering::Relaxed);
+        let tail = shared.meta.tail.load(Ordering::Acquire);
+        if shared.distance(head, tail) == shared.buffer.len() {
+            self.note_full();
+            return SendStatus::Full;
+        }
+        unsafe {
+            (*shared.slot(head)).write(el.take().unwrap());
+            self.publish(head, 1);
+        }
+        SendStatus::Sent
+    }
+
+    // Moves over to the buffer the receiver left with `Receiver::shrink_to`,
+    // if it asked for that. Every write checks first.
+    #[inline]
+    fn resize_if_asked(&mut self) {
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.state.load(Ordering::Relaxed) & RESIZING != 0 {
+            self.resize();
+        }
+    }
+
+    #[cold]
+    fn resize(&mut self) {
+        let old_ptr = self.ptr;
+        let old = unsafe { old_ptr.as_ref() };
+        let Some(next) = NonNull::new(old.meta.next.load(Ordering::Acquire)) else {
+            return;
+        };
+        // Whatever is still queued stays where it is, so only move once the
+        // receiver has taken all of it. Nothing is written here afterwards,
+        // which is how the receiver knows it is done with the old buffer.
+        let head = old.meta.head.load(Ordering::Relaxed);
+        if old.meta.tail.load(Ordering::Acquire) != head {
+            return;
+        }
+        // The new buffer lives at least as long as the old one.
+        let new_ptr = unsafe { Shared::<T, A>::from_thin(next) };
+        let new = unsafe { new_ptr.as_ref() };
+        // Positions carry on as they are, which needs them below the new
+        // buffer's wrap point and the acknowledged one not wrapped around
+        // behind `head`. Otherwise they have to wrap here first.
+        let wrap = new.meta.wrap;
+        if (wrap != 0 && head >= wrap) || old.meta.acked.load(Ordering::Relaxed) > head {
+            return;
+        }
+        // `SeqCst` orders this against `Receiver::ack`, so an
+        // acknowledgement is either read below or repeated in the new buffer.
+        old.meta.state.fetch_or(MOVING, Ordering::SeqCst);
+        new.meta.head.store(head, Ordering::Relaxed);
+        new.meta.tail.store(head, Ordering::Relaxed);
+        let acked = old.meta.acked.load(Ordering::SeqCst);
+        new.meta.acked.store(acked, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        {
+            let (old, new) = (&old.meta, &new.meta);
+            let sent = old.total_sent.load(Ordering::Relaxed);
+            new.total_sent.store(sent, Ordering::Relaxed);
+            let full = old.send_full_rejections.load(Ordering::Relaxed);
+            new.send_full_rejections.store(full, Ordering::Relaxed);
+            let blocked = old.last_send_blocked.load(Ordering::Relaxed);
+            new.last_send_blocked.store(blocked, Ordering::Relaxed);
+        }
+        let state = old.meta.state.load(Ordering::Relaxed);
+        new.meta
+            .state
+            .fetch_or(state & (DRAINING | CLOSING), Ordering::Relaxed);
+        // Hands the new buffer to the receiver. A shutdown it acknowledged
+        // before this comes along; one after sees `MOVING` and is repeated.
+        let state = old.meta.state.fetch_or(MOVED, Ordering::AcqRel);
+        new.meta
+            .state
+            .fetch_or(state & SHUTDOWN_ACKED, Ordering::Relaxed);
+        self.ptr = new_ptr;
+        // Wakes a receiver waiting on the old buffer, so it comes over.
+        if old.release(TX, TX_WAKING, RX, &old.meta.rx_waker) {
+            unsafe { Shared::free(old_ptr) };
+        }
+    }
+
+    // Counts a send turned away by a full queue.
+    fn note_full(&self) {
+        #[cfg(feature = "metrics")]
+        {
+            let shared = unsafe { self.ptr.as_ref() };
+            shared
+                .meta
+                .send_full_rejections
+                .fetch_add(1, Ordering::Relaxed);
+            shared.meta.last_send_blocked.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Moves `head` past the `n` slots starting at `head` and lets the
+    /// receiver know.
+    ///
+    /// # Safety
+    ///
+    /// `head` must be the current head, and the `n` slots from it must be
+    /// free and written.
+    unsafe fn publish(&mut self, head: usize, n: usize) {
+        let shared = unsafe { self.ptr.as_ref() };
+        unsafe { self.publish_to(shared.advance(head, n), n) };
+    }
+
+    /// Moves `head` to `new_head`, `n` slots further on, and lets the
+    /// receiver know.
+    ///
+    /// This is the only place `head` moves forward, which is what keeps every
+    /// slot between `tail` and `head` initialized for the receiver and for
+    /// the teardown in `Shared::drop`.
+    ///
+    /// # Safety
+    ///
+    /// The `n` slots from the current head to `new_head` must be free and
+    /// written.
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    unsafe fn publish_to(&mut self, new_head: usize, n: usize) {
+        let shared = unsafe { self.ptr.as_ref() };
+        #[cfg(all(test, not(loom)))]
+        for _ in 0..PUBLISH_DELAY.load(core::sync::atomic::Ordering::Relaxed) {
+            core::hint::spin_loop();
+        }
+        shared.meta.head.store(new_head, Ordering::Release);
+        #[cfg(feature = "metrics")]
+        {
+            shared.meta.total_sent.fetch_add(n, Ordering::Relaxed);
             shared
                 .meta
-                .head
-                .store(head.wrapping_add(1), Ordering::Release);
-            None
+                .last_send_blocked
+                .store(false, Ordering::Relaxed);
+        }
+        #[cfg(feature = "heartbeat")]
+        shared.beat();
+        shared.meta.rx_waker.wake();
+    }
+
+    /// Sends `el`, calling `on_full` each time the queue is found full before
+    /// trying again.
+    ///
+    /// `on_full` decides how to wait, whether by spinning, sleeping or
+    /// recording the stall, so this works without `std` too. Fails if the
+    /// receiver is gone.
+    pub fn send_with_backoff(
+        &mut self,
+        mut el: T,
+        mut on_full: impl FnMut(),
+    ) -> Result<(), SendError<T>> {
+        loop {
+            match self.try_send(el) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(e)) => return Err(SendError(e)),
+                Err(TrySendError::Full(e)) => {
+                    el = e;
+                    on_full();
+                }
+            }
+        }
+    }
+
+    /// Sends an element like [`try_send`](Self::try_send), handing it back
+    /// together with the current [`spare_capacity`](Self::spare_capacity) on
+    /// failure.
+    ///
+    /// A full queue reports no spare room, which may have grown again by the
+    /// time the caller looks. Room to spare means the receiver is gone.
+    pub fn try_send_checked(&mut self, el: T) -> Result<(), (T, usize)> {
+        self.try_send(el)
+            .map_err(|e| (e.into_inner(), self.spare_capacity()))
+    }
+
+    /// Sends an element like [`try_send`](Self::try_send) and returns the
+    /// position it was written at.
+    ///
+    /// Positions count up from zero with every element sent and are the same
+    /// raw counters [`debug_positions`](Self::debug_positions) reports.
+    pub fn try_send_indexed(&mut self, el: T) -> Result<usize, TrySendError<T>> {
+        // Only the sender moves `head`, and a resize keeps it as it is.
+        self.resize_if_asked();
+        let head = unsafe { self.ptr.as_ref() }
+            .meta
+            .head
+            .load(Ordering::Relaxed);
+        self.try_send(el).map(|()| head)
+    }
+
+    /// Copies as many elements from the front of `src` as currently fit and
+    /// returns how many were sent.
+    pub fn try_send_slice(&mut self, src: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        self.resize_if_asked();
+        let shared = unsafe { self.ptr.as_ref() };
+        let cap = shared.buffer.len();
+        let head = shared.meta.head.load(Ordering::Relaxed);
+        let tail = shared.meta.tail.load(Ordering::Acquire);
+        let n = src.len().min(cap - shared.distance(head, tail));
+        if n == 0 {
+            return 0;
+        }
+        // Fill up to the end of the buffer, then wrap around to the start.
+        let start = shared.index(head);
+        let first = n.min(cap - start);
+        let dst = shared.slots();
+        unsafe {
+            core::ptr::copy_nonoverlapping(src.as_ptr(), dst.add(start), first);
+            core::ptr::copy_nonoverlapping(src.as_ptr().add(first), dst, n - first);
+            self.publish(head, n);
+        }
+        n
+    }
+
+    /// Sends elements from `iter` until it runs dry or the queue fills up and
+    /// returns how many were sent.
+    ///
+    /// An element is only taken from `iter` once there is room for it. If
+    /// `iter` panics, nothing from this call is sent and the elements it had
+    /// yielded so far are dropped.
+    pub fn send_iter<I: IntoIterator<Item = T>>(&mut self, iter: I) -> usize {
+        // Drops what was written so far if `iter` panics.
+        struct Unpublished<'a, T, A: Allocator> {
+            shared: &'a Shared<T, A>,
+            head: usize,
+            n: usize,
+        }
+
+        impl<T, A: Allocator> Drop for Unpublished<'_, T, A> {
+            fn drop(&mut self) {
+                for i in 0..self.n {
+                    let slot = self.shared.slot(self.shared.advance(self.head, i));
+                    unsafe { (*slot).assume_init_drop() };
+                }
+            }
+        }
+
+        self.resize_if_asked();
+        let shared = unsafe { self.ptr.as_ref() };
+        let head = shared.meta.head.load(Ordering::Relaxed);
+        let tail = shared.meta.tail.load(Ordering::Acquire);
+        let free = shared.buffer.len() - shared.distance(head, tail);
+        let mut iter = iter.into_iter();
+        let mut written = Unpublished { shared, head, n: 0 };
+        while written.n < free {
+            let Some(el) = iter.next() else { break };
+            unsafe { (*shared.slot(shared.advance(head, written.n))).write(el) };
+            written.n += 1;
+        }
+        let n = mem::replace(&mut written.n, 0);
+        if n > 0 {
+            // Publish the whole batch at once.
+            unsafe { self.publish(head, n) };
+        }
+        n
+    }
+
+    /// Hands out the next free slot so a value can be built right in the
+    /// queue, or returns `None` if the queue is full.
+    ///
+    /// Nothing is sent until the slot is committed.
+    pub fn reserve(&mut self) -> Option<Slot<'_, T, A>> {
+        self.resize_if_asked();
+        let shared = unsafe { self.ptr.as_ref() };
+        let head = shared.meta.head.load(Ordering::Relaxed);
+        let tail = shared.meta.tail.load(Ordering::Acquire);
+        if shared.distance(head, tail) == shared.buffer.len() {
+            return None;
+        }
+        Some(Slot { sender: self, head })
+    }
+
+    /// Hands out up to `max` free slots in one contiguous run, so they can be
+    /// filled in place, for example by reading from a socket straight into
+    /// them.
+    ///
+    /// The run stops early at the end of the buffer, and is empty if the
+    /// queue is full. Nothing is sent until the run is committed.
+    pub fn reserve_slice(&mut self, max: usize) -> Slots<'_, T, A> {
+        self.resize_if_asked();
+        let shared = unsafe { self.ptr.as_ref() };
+        let cap = shared.buffer.len();
+        let head = shared.meta.head.load(Ordering::Relaxed);
+        let tail = shared.meta.tail.load(Ordering::Acquire);
+        let len = max
+            .min(cap - shared.distance(head, tail))
+            .min(cap - shared.index(head));
+        Slots {
+            sender: self,
+            head,
+            len,
+        }
+    }
+
+    /// Hands out up to `max` free slots as two runs, one up to the end of
+    /// the buffer and one continuing from its start, so a fill can go
+    /// across the wrap.
+    ///
+    /// The second run is empty unless the first one reaches the end of the
+    /// buffer. Both go out together with
+    /// [`commit_wrapped`](WrappedSlots::commit_wrapped).
+    pub fn reserve_wrapped(&mut self, max: usize) -> WrappedSlots<'_, T, A> {
+        self.resize_if_asked();
+        let shared = unsafe { self.ptr.as_ref() };
+        let cap = shared.buffer.len();
+        let head = shared.meta.head.load(Ordering::Relaxed);
+        let tail = shared.meta.tail.load(Ordering::Acquire);
+        let len = max.min(cap - shared.distance(head, tail));
+        let first = len.min(cap - shared.index(head));
+        WrappedSlots {
+            sender: self,
+            head,
+            first,
+            second: len - first,
+        }
+    }
+
+    /// Sends the value `f` builds, calling it only once there is room.
+    ///
+    /// Nothing is built when the queue is full or the receiver is gone. If
+    /// `f` panics, nothing is sent.
+    pub fn try_send_with<F: FnOnce() -> T>(&mut self, f: F) -> Result<(), TrySendError<()>> {
+        if !self.is_connected() {
+            return Err(TrySendError::Disconnected(()));
+        }
+        match self.reserve() {
+            Some(slot) => {
+                slot.write(f());
+                Ok(())
+            }
+            None => {
+                self.note_full();
+                Err(TrySendError::Full(()))
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    /// Sends `el`, blocking while the queue is full.
+    ///
+    /// Returns the element back if the receiver is gone.
+    pub fn send(&mut self, el: T) -> Result<(), SendError<T>> {
+        self.send_deadline(el, None, None)
+            .map_err(|e| SendError(e.into_inner()))
+    }
+
+    #[cfg(feature = "std")]
+    /// Blocks until there is room for at least `min` elements and returns
+    /// how much there is, so a batch can follow in one go.
+    ///
+    /// Fails if the receiver goes away first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min` is more than the capacity.
+    pub fn wait_for_space(&mut self, min: usize) -> Result<usize, SendError<()>> {
+        self.resize_if_asked();
+        assert!(
+            min <= self.capacity(),
+            "waiting for more space than the queue has"
+        );
+        let ptr = self.ptr;
+        let slot = unsafe { &ptr.as_ref().meta.tx_waker };
+        waker::block_on(&[slot], None, None, || {
+            if !self.is_connected() {
+                return Some(Err(SendError(())));
+            }
+            let spare = self.spare_capacity();
+            (spare >= min).then_some(Ok(spare))
+        })
+        .unwrap_or(Err(SendError(())))
+    }
+
+    #[cfg(feature = "std")]
+    /// Blocks until the receiver has taken out everything sent so far, for
+    /// example to know all of it was handled before shutting down.
+    ///
+    /// Fails if the receiver goes away with elements still queued.
+    pub fn flush(&mut self) -> Result<(), SendError<()>> {
+        let ptr = self.ptr;
+        let slot = unsafe { &ptr.as_ref().meta.tx_waker };
+        waker::block_on(&[slot], None, None, || {
+            // A receiver that is gone won't move `tail` again, so look at
+            // the connection first.
+            let connected = self.is_connected();
+            if self.is_empty() {
+                Some(Ok(()))
+            } else {
+                (!connected).then_some(Err(SendError(())))
+            }
+        })
+        .unwrap_or(Err(SendError(())))
+    }
+
+    #[cfg(feature = "std")]
+    /// Sends all of `src`, copying in as much as fits and blocking whenever
+    /// the queue is full.
+    ///
+    /// Fails if the receiver goes away first. Part of `src` may have been
+    /// sent by then.
+    pub fn send_all(&mut self, mut src: &[T]) -> Result<(), SendError<()>>
+    where
+        T: Copy,
+    {
+        self.block_on(None, None, |tx| {
+            if !tx.is_connected() {
+                return Some(Err(SendError(())));
+            }
+            src = &src[tx.try_send_slice(src)..];
+            src.is_empty().then_some(Ok(()))
+        })
+        .unwrap_or(Err(SendError(())))
+    }
+
+    #[cfg(feature = "std")]
+    /// Sends `el` like [`send`](Self::send), but polls the queue up to
+    /// `spins` times before parking.
+    ///
+    /// Spinning trades CPU time for a quicker handoff when the receiver is
+    /// expected to make room soon.
+    pub fn send_spin(&mut self, el: T, spins: u32) -> Result<(), SendError<T>> {
+        self.send_deadline(el, Some(spins), None)
+            .map_err(|e| SendError(e.into_inner()))
+    }
+
+    #[cfg(feature = "std")]
+    /// Sends `el`, blocking for at most `dur` while the queue is full.
+    pub fn send_timeout(&mut self, el: T, dur: Duration) -> Result<(), SendTimeoutError<T>> {
+        self.send_deadline(el, None, Instant::now().checked_add(dur))
+    }
+
+    #[cfg(feature = "std")]
+    fn send_deadline(
+        &mut self,
+        el: T,
+        spins: Option<u32>,
+        deadline: Option<Instant>,
+    ) -> Result<(), SendTimeoutError<T>> {
+        let mut el = Some(el);
+        self.block_on(spins, deadline, |tx| {
+            match tx.try_send(el.take().unwrap()) {
+                Ok(()) => Some(Ok(())),
+                Err(TrySendError::Disconnected(e)) => Some(Err(SendTimeoutError::Disconnected(e))),
+                Err(TrySendError::Full(e)) => {
+                    el = Some(e);
+                    None
+                }
+            }
+        })
+        .unwrap_or_else(|| Err(SendTimeoutError::Timeout(el.take().unwrap())))
+    }
+
+    // `waker::block_on` on our waker slot, for an `op` that sends. Moving
+    // over to a new buffer frees the slot being waited on, so that starts
+    // over on the new buffer's.
+    #[cfg(feature = "std")]
+    fn block_on<R>(
+        &mut self,
+        spins: Option<u32>,
+        deadline: Option<Instant>,
+        mut op: impl FnMut(&mut Self) -> Option<R>,
+    ) -> Option<R> {
+        loop {
+            let ptr = self.ptr;
+            let slot = unsafe { &ptr.as_ref().meta.tx_waker };
+            let res = waker::block_on(&[slot], spins, deadline, || match op(self) {
+                Some(r) => Some(Some(r)),
+                None if self.ptr.cast::<()>() != ptr.cast() => Some(None),
+                None => None,
+            });
+            match res {
+                Some(None) => {}
+                res => return res.flatten(),
+            }
+        }
+    }
+
+    /// Returns a future that sends `el` once there is room in the queue.
+    ///
+    /// Dropping the future before it completes leaves the queue untouched.
+    #[cfg(feature = "async")]
+    pub fn send_async(&mut self, el: T) -> SendFuture<'_, T, A> {
+        SendFuture {
+            sender: self,
+            el: Some(el),
+        }
+    }
+
+    /// Tries to send the element in `el`, registering `cx`'s waker to be
+    /// woken once there is room if the queue is full.
+    ///
+    /// The element is taken out of `el` once it is sent or handed back in
+    /// the error, and stays put while this returns `Pending`. Calling this
+    /// with `el` empty returns `Ready(Ok(()))` right away.
+    #[cfg(feature = "async")]
+    pub fn poll_send(
+        &mut self,
+        cx: &mut Context<'_>,
+        el: &mut Option<T>,
+    ) -> Poll<Result<(), SendError<T>>> {
+        // The buffer whose slot holds our waker. Moving over to another one
+        // means registering again there.
+        let mut registered = None;
+        loop {
+            let Some(value) = el.take() else {
+                return Poll::Ready(Ok(()));
+            };
+            match self.try_send(value) {
+                Ok(()) => return Poll::Ready(Ok(())),
+                Err(TrySendError::Disconnected(value)) => {
+                    return Poll::Ready(Err(SendError(value)));
+                }
+                Err(TrySendError::Full(value)) => *el = Some(value),
+            }
+            if registered == Some(self.ptr) {
+                return Poll::Pending;
+            }
+            // Check once more after registering so a slot freed in between
+            // isn't missed.
+            unsafe { self.ptr.as_ref() }
+                .meta
+                .tx_waker
+                .register(cx.waker());
+            registered = Some(self.ptr);
+        }
+    }
+
+    /// Returns the number of elements the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        unsafe { self.ptr.as_ref() }.buffer.len()
+    }
+
+    /// Returns the number of bytes the slots take up, zero for zero-sized
+    /// elements.
+    ///
+    /// Any padding `T` itself needs for its alignment is counted, but the
+    /// queue's header isn't; [`Scoped::size_for`] gives the size of the whole
+    /// allocation.
+    pub const fn byte_capacity(&self) -> usize {
+        self.capacity() * size_of::<T>()
+    }
+
+    /// Returns the number of elements currently in the queue.
+    ///
+    /// The receiver may be draining concurrently, so this is an upper bound.
+    pub fn len(&self) -> usize {
+        let shared = unsafe { self.ptr.as_ref() };
+        let head = shared.meta.head.load(Ordering::Relaxed);
+        let tail = shared.meta.tail.load(Ordering::Acquire);
+        shared.len(head, tail)
+    }
+
+    /// Returns the number of elements like [`len`](Self::len), or `None` if
+    /// the queue's positions say more are queued than fit.
+    ///
+    /// That can only happen if something corrupted the queue, which `len`
+    /// only catches in debug builds.
+    pub fn len_checked(&self) -> Option<usize> {
+        let shared = unsafe { self.ptr.as_ref() };
+        let head = shared.meta.head.load(Ordering::Relaxed);
+        let tail = shared.meta.tail.load(Ordering::Acquire);
+        shared.checked_len(head, tail)
+    }
+
+    /// Panics if the queue's positions or handle bookkeeping don't add up.
+    ///
+    /// For fuzzers and property tests to call after every step. The
+    /// receiver may be moving concurrently, which never breaks what is
+    /// checked.
+    #[cfg(any(test, feature = "debug-checks"))]
+    pub fn assert_invariants(&self) {
+        unsafe { self.ptr.as_ref() }.assert_invariants(TX);
+    }
+
+    /// Returns `true` if the queue is empty. See [`Sender::len`].
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the queue is full. See [`Sender::len`].
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Returns how many elements can be sent right now without the queue
+    /// filling up.
+    ///
+    /// The receiver may be freeing slots concurrently, so this is a lower
+    /// bound.
+    pub fn spare_capacity(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// Returns the queue's counters.
+    ///
+    /// Each counter is read on its own, so ones the receiver updates may be
+    /// slightly behind.
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> Stats {
+        unsafe { self.ptr.as_ref() }.stats()
+    }
+
+    /// Returns whether the last [`try_send`](Self::try_send) that got past the
+    /// disconnect check found the queue full.
+    ///
+    /// A producer can use it to slow down without keeping a counter of its
+    /// own.
+    #[cfg(feature = "metrics")]
+    pub fn last_send_blocked(&self) -> bool {
+        unsafe { self.ptr.as_ref() }
+            .meta
+            .last_send_blocked
+            .load(Ordering::Relaxed)
+    }
+
+    /// Returns the position up to which the receiver has acknowledged
+    /// processing elements with [`Receiver::ack`].
+    ///
+    /// Compare it with the positions [`try_send_indexed`](Self::try_send_indexed)
+    /// returns to hold off sending while too much is unprocessed.
+    pub fn acked_position(&self) -> usize {
+        unsafe { self.ptr.as_ref() }
+            .meta
+            .acked
+            .load(Ordering::Acquire)
+    }
+
+    /// Returns the raw `(head, tail)` counters, for monitoring and tests.
+    ///
+    /// The two are read one after the other rather than together, so this is
+    /// a best-effort snapshot. The counters aren't reduced to slot indices.
+    pub fn debug_positions(&self) -> (usize, usize) {
+        unsafe { self.ptr.as_ref() }.positions()
+    }
+
+    /// Returns `true` if the receiver is still around.
+    ///
+    /// The receiver may be dropped right after this returns, so treat `true`
+    /// as a hint. `false` is final.
+    pub fn is_connected(&self) -> bool {
+        unsafe { self.ptr.as_ref() }.is_present(RX, Ordering::Acquire)
+    }
+
+    /// Hands out a new receiver for the queue once the previous one is gone.
+    ///
+    /// The new receiver picks up with whatever the old one left queued.
+    /// Returns `None` while a receiver is still around.
+    pub fn undrop_receiver(&self) -> Option<Receiver<T, A>> {
+        let shared = unsafe { self.ptr.as_ref() };
+        // A receiver that is still waking us counts as around.
+        shared
+            .meta
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |state| {
+                (state & (RX | RX_WAKING) == 0).then_some(state | RX)
+            })
+            .ok()?;
+        // The old receiver let go of a buffer it left for us as well.
+        if let Some(next) = NonNull::new(shared.meta.next.load(Ordering::Relaxed)) {
+            let next = unsafe { Shared::<T, A>::from_thin(next).as_ref() };
+            next.meta.state.fetch_or(RX, Ordering::Relaxed);
+        }
+        Some(Receiver::from_shared(self.ptr))
+    }
+
+    /// Turns the sender into a raw pointer, for example to hand it across an
+    /// FFI boundary.
+    ///
+    /// The sender stays alive until it is rebuilt with
+    /// [`from_raw`](Self::from_raw) and dropped.
+    pub fn into_raw(self) -> NonNull<()> {
+        ManuallyDrop::new(self).ptr.cast()
+    }
+
+    /// Rebuilds a sender from a pointer returned by
+    /// [`into_raw`](Self::into_raw).
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Sender::<T, A>::into_raw` and each pointer must
+    /// be rebuilt exactly once.
+    pub unsafe fn from_raw(ptr: NonNull<()>) -> Self {
+        Sender {
+            ptr: unsafe { Shared::from_thin(ptr) },
         }
     }
+
+    /// Ends the stream.
+    ///
+    /// The receiver still gets every element sent so far and then sees the
+    /// queue as disconnected, same as after a drop. Unlike a drop, which may
+    /// just as well come from a panic unwinding past the sender, this is
+    /// reported by [`Receiver::is_closed`].
+    ///
+    /// A receiver blocked waiting for more elements is woken right away to
+    /// see the end of the stream.
+    pub fn close(self) {
+        unsafe { self.ptr.as_ref() }
+            .meta
+            .state
+            .fetch_or(CLOSED, Ordering::Relaxed);
+    }
+
+    /// Asks the receiver to finish up, which it sees through
+    /// [`Receiver::is_draining`].
+    ///
+    /// The sender can still send afterwards; this only tells the receiver
+    /// that the end is near. A receiver blocked waiting for elements isn't
+    /// woken by it, so a consumer taking part in the handshake should check
+    /// between receives, or wait with a timeout.
+    pub fn begin_shutdown(&mut self) {
+        unsafe { self.ptr.as_ref() }
+            .meta
+            .state
+            .fetch_or(DRAINING, Ordering::Release);
+    }
+
+    /// Waits for the receiver to answer [`begin_shutdown`](Self::begin_shutdown)
+    /// with [`Receiver::ack_shutdown`], then ends the stream like
+    /// [`close`](Self::close).
+    ///
+    /// Everything the receiver did before acknowledging is visible once this
+    /// returns, so a pipeline can be sure every element was handled before
+    /// it exits. Begins the shutdown first if that hasn't happened yet.
+    ///
+    /// Fails if the receiver goes away without acknowledging.
+    #[cfg(feature = "std")]
+    pub fn finish_shutdown(mut self) -> Result<(), SendError<()>> {
+        self.begin_shutdown();
+        let ptr = self.ptr;
+        let shared = unsafe { ptr.as_ref() };
+        waker::block_on(&[&shared.meta.tx_waker], None, None, || {
+            // The receiver acknowledges before it can go away, so look at
+            // the connection first.
+            let connected = self.is_connected();
+            if shared.meta.state.load(Ordering::Acquire) & SHUTDOWN_ACKED != 0 {
+                Some(Ok(()))
+            } else {
+                (!connected).then_some(Err(SendError(())))
+            }
+        })
+        .unwrap_or(Err(SendError(())))
+        .inspect(|()| self.close())
+    }
 }
 
-impl<T> Drop for Sender<T> {
+/// Sends elements like [`Sender::send_iter`], stopping without complaint
+/// once the queue is full.
+///
+/// Whatever doesn't fit is left in the iterator and dropped with it.
+impl<T, A: Allocator> Extend<T> for Sender<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.send_iter(iter);
+    }
+}
+
+impl<T, A: Allocator> Drop for Sender<T, A> {
     fn drop(&mut self) {
-        let shared = unsafe { self.ptr.as_mut() };
-        if shared.meta.rx_dropped.load(Ordering::Acquire) {
-            drop(unsafe { Box::from_non_null(self.ptr) });
-        } else {
-            shared.meta.tx_dropped.store(true, Ordering::Release);
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.state.load(Ordering::Relaxed) & CLOSING != 0 {
+            shared.meta.state.fetch_or(CLOSED, Ordering::Relaxed);
+        }
+        if shared.release(TX, TX_WAKING, RX, &shared.meta.rx_waker) {
+            unsafe { Shared::free(self.ptr) };
         }
     }
 }
 
+/// A free slot handed out by [`Sender::reserve`].
+///
+/// It derefs to the uninitialized slot. Dropping it without committing sends
+/// nothing, and anything written into it is leaked rather than dropped. This
+/// includes unwinding out of a panic while the value is being built, so the
+/// receiver never sees a half-written element.
 #[derive(Debug)]
-pub struct Receiver<T> {
-    ptr: NonNull<Shared<T>>,
+pub struct Slot<'a, T, A: Allocator = Global> {
+    sender: &'a mut Sender<T, A>,
+    head: usize,
 }
 
-impl<T> Receiver<T> {
-    pub fn try_recv(&mut self) -> Option<T> {
-        let shared = &mut unsafe { self.ptr.as_mut() };
-        let tail = shared.meta.tail.load(Ordering::Relaxed);
-        let head = shared.meta.head.load(Ordering::Acquire);
-        // If tail == head, then the queue is empty.
-        if tail == head {
-            None
-        } else {
-            shared
-                .meta
-                .tail
-                .store(tail.wrapping_add(1), Ordering::Release);
-            Some(unsafe {
-                std::mem::replace(
-                    &mut shared.buffer[tail % shared.buffer.len()],
-                    MaybeUninit::uninit(),
-                )
-                .assume_init()
-            })
-        }
+impl<T, A: Allocator> Slot<'_, T, A> {
+    /// Writes `el` into the slot and sends it.
+    pub fn write(mut self, el: T) {
+        (*self).write(el);
+        unsafe { self.commit() }
+    }
+
+    /// Sends the value that was written into the slot.
+    ///
+    /// # Safety
+    ///
+    /// The slot must have been initialized.
+    pub unsafe fn commit(self) {
+        unsafe { self.sender.publish(self.head, 1) };
     }
 }
 
-unsafe impl<T> Send for Receiver<T> {}
+/// A run of free slots handed out by [`Sender::reserve_slice`].
+///
+/// It derefs to the uninitialized slots. As with [`Slot`], dropping it
+/// without committing sends nothing and leaks whatever was written.
+#[derive(Debug)]
+pub struct Slots<'a, T, A: Allocator = Global> {
+    sender: &'a mut Sender<T, A>,
+    head: usize,
+    len: usize,
+}
 
-impl<T> Drop for Receiver<T> {
-    fn drop(&mut self) {
-        let shared = unsafe { self.ptr.as_mut() };
-        if shared.meta.tx_dropped.load(Ordering::Acquire) {
-            drop(unsafe { Box::from_non_null(self.ptr) });
-        } else {
-            shared.meta.rx_dropped.store(true, Ordering::Release);
+impl<T, A: Allocator> Slots<'_, T, A> {
+    /// Sends the first `n` slots of the run.
+    ///
+    /// # Safety
+    ///
+    /// The first `n` slots must have been initialized.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is more than the length of the run.
+    pub unsafe fn commit(self, n: usize) {
+        assert!(n <= self.len, "committing more slots than were reserved");
+        if n > 0 {
+            unsafe { self.sender.publish(self.head, n) };
         }
     }
 }
 
-pub fn new<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
-    let layout = Layout::new::<Meta>()
-        .extend(Layout::array::<T>(cap).unwrap())
-        .unwrap();
-    let ptr = NonNull::new(unsafe { alloc(layout.0.pad_to_align()) })
-        .unwrap()
-        .cast();
-    unsafe {
-        ptr.write(Meta {
-            tx_dropped: false.into(),
-            rx_dropped: false.into(),
-            head: 0.into(),
-            tail: 0.into(),
-        });
-    };
-    let thing = NonNull::from_raw_parts(ptr, cap);
-    (Sender { ptr: thing }, Receiver { ptr: thing })
+impl<T, A: Allocator> Deref for Slots<'_, T, A> {
+    type Target = [MaybeUninit<T>];
+
+    fn deref(&self) -> &[MaybeUninit<T>] {
+        let shared = unsafe { self.sender.ptr.as_ref() };
+        // `slot` only covers one slot, so offset from the start instead.
+        let start = unsafe { shared.slots().add(shared.index(self.head)) };
+        unsafe { core::slice::from_raw_parts(start.cast(), self.len) }
+    }
 }
 
-#[cfg(test)]
-mod test {
-    use crate::spsc::new;
+impl<T, A: Allocator> DerefMut for Slots<'_, T, A> {
+    fn deref_mut(&mut self) -> &mut [MaybeUninit<T>] {
+        let shared = unsafe { self.sender.ptr.as_ref() };
+        let start = unsafe { shared.slots().add(shared.index(self.head)) };
+        unsafe { core::slice::from_raw_parts_mut(start.cast(), self.len) }
+    }
+}
+
+/// Two runs of free slots handed out by [`Sender::reserve_wrapped`].
+///
+/// As with [`Slots`], dropping it without committing sends nothing and leaks
+/// whatever was written.
+#[derive(Debug)]
+pub struct WrappedSlots<'a, T, A: Allocator = Global> {
+    sender: &'a mut Sender<T, A>,
+    head: usize,
+    first: usize,
+    second: usize,
+}
+
+impl<T, A: Allocator> WrappedSlots<'_, T, A> {
+    /// Returns the run up to the end of the buffer and the one after it.
+    pub fn as_mut_slices(&mut self) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        let shared = unsafe { self.sender.ptr.as_ref() };
+        let start = shared.slots();
+        // The second run ends before the first begins, or the queue would
+        // have had less room than that.
+        unsafe {
+            (
+                core::slice::from_raw_parts_mut(
+                    start.add(shared.index(self.head)).cast(),
+                    self.first,
+                ),
+                core::slice::from_raw_parts_mut(start.cast(), self.second),
+            )
+        }
+    }
+
+    /// Sends the first `first` slots of the first run and the first `second`
+    /// slots of the second run.
+    ///
+    /// Both are published with one move of `head`, so the receiver never sees
+    /// the first run without the second.
+    ///
+    /// # Safety
+    ///
+    /// Those slots must have been initialized.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either count is more than the length of its run, or if
+    /// `second` is nonzero while `first` leaves part of the first run out,
+    /// which would leave a gap.
+    pub unsafe fn commit_wrapped(self, first: usize, second: usize) {
+        assert!(
+            first <= self.first && second <= self.second,
+            "committing more slots than were reserved"
+        );
+        assert!(
+            second == 0 || first == self.first,
+            "committed runs leave a gap"
+        );
+        if first + second > 0 {
+            unsafe { self.sender.publish(self.head, first + second) };
+        }
+    }
+}
+
+impl<T, A: Allocator> Deref for Slot<'_, T, A> {
+    type Target = MaybeUninit<T>;
+
+    fn deref(&self) -> &MaybeUninit<T> {
+        let shared = unsafe { self.sender.ptr.as_ref() };
+        unsafe { &*shared.slot(self.head) }
+    }
+}
+
+impl<T, A: Allocator> DerefMut for Slot<'_, T, A> {
+    fn deref_mut(&mut self) -> &mut MaybeUninit<T> {
+        let shared = unsafe { self.sender.ptr.as_ref() };
+        unsafe { &mut *shared.slot(self.head) }
+    }
+}
+
+/// Future returned by [`Sender::send_async`].
+#[cfg(feature = "async")]
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct SendFuture<'a, T, A: Allocator = Global> {
+    sender: &'a mut Sender<T, A>,
+    el: Option<T>,
+}
+
+// The element is only ever moved out whole, never pinned in place.
+#[cfg(feature = "async")]
+impl<T, A: Allocator> Unpin for SendFuture<'_, T, A> {}
+
+#[cfg(feature = "async")]
+impl<T, A: Allocator> Future for SendFuture<'_, T, A> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        assert!(this.el.is_some(), "SendFuture polled after completion");
+        this.sender.poll_send(cx, &mut this.el)
+    }
+}
+
+pub struct Receiver<T, A: Allocator = Global> {
+    ptr: NonNull<Shared<T, A>>,
+    // Kept here rather than next to the other statistics since nobody else
+    // reads it, and boxed so it doesn't make the handle any bigger either.
+    #[cfg(all(feature = "metrics", feature = "std"))]
+    log: Box<RecvLog>,
+}
+
+impl<T, A: Allocator> Receiver<T, A> {
+    fn from_shared(ptr: NonNull<Shared<T, A>>) -> Self {
+        Receiver {
+            ptr,
+            #[cfg(all(feature = "metrics", feature = "std"))]
+            log: Box::new(RecvLog::new()),
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let tail = self.front()?;
+        let shared = unsafe { self.ptr.as_ref() };
+        let el = unsafe { (*shared.slot(tail)).assume_init_read() };
+        self.pop_front(tail, 1);
+        Ok(el)
+    }
+
+    /// The old `try_recv`, returning `None` whether the queue was empty or
+    /// the sender gone as well.
+    #[deprecated(note = "use `try_recv`, which tells an empty queue from a disconnected one")]
+    pub fn try_recv_opt(&mut self) -> Option<T> {
+        self.try_recv().ok()
+    }
+
+    /// Receives an element, calling `on_empty` each time the queue is found
+    /// empty before trying again.
+    ///
+    /// The counterpart of [`Sender::send_with_backoff`]. Fails once the
+    /// sender is gone and the queue has been drained.
+    pub fn recv_with_backoff(&mut self, mut on_empty: impl FnMut()) -> Result<T, RecvError> {
+        loop {
+            match self.try_recv() {
+                Ok(el) => return Ok(el),
+                Err(TryRecvError::Disconnected) => return Err(RecvError),
+                Err(TryRecvError::Empty) => on_empty(),
+            }
+        }
+    }
+
+    /// Receives the front element without checking that there is one.
+    ///
+    /// Meant for loops that already know how many elements are queued, for
+    /// example from [`len`](Self::len), since only the sender adds elements
+    /// and those stay until we take them.
+    ///
+    /// # Safety
+    ///
+    /// The queue must not be empty. Calling this on an empty queue reads an
+    /// uninitialized or already received slot, which is undefined behavior.
+    pub unsafe fn try_recv_unchecked(&mut self) -> T {
+        self.catch_up();
+        let shared = unsafe { self.ptr.as_ref() };
+        let tail = shared.meta.tail.load(Ordering::Relaxed);
+        // Pairs with the sender's `Release` even though we skip the check.
+        let head = shared.meta.head.load(Ordering::Acquire);
+        debug_assert_ne!(tail, head, "try_recv_unchecked on an empty queue");
+        let el = unsafe { (*shared.slot(tail)).assume_init_read() };
+        self.pop_front(tail, 1);
+        el
+    }
+
+    /// Like [`try_recv`](Self::try_recv), but yields the thread to the
+    /// scheduler before reporting an empty queue.
+    ///
+    /// Meant for simple polling loops that do other work between attempts:
+    /// retrying on [`Empty`](TryRecvError::Empty) and stopping on
+    /// [`Disconnected`](TryRecvError::Disconnected) then doesn't keep a core
+    /// busy. A loop that only waits for the next element is better off with
+    /// [`recv`](Self::recv), which sleeps until the sender wakes it.
+    #[cfg(feature = "std")]
+    pub fn try_recv_yielding(&mut self) -> Result<T, TryRecvError> {
+        let res = self.try_recv();
+        if let Err(TryRecvError::Empty) = res {
+            std::thread::yield_now();
+        }
+        res
+    }
+
+    /// Receives an element, or returns `T::default()` if there is none.
+    ///
+    /// Never blocks. A default value comes back both while the sender is
+    /// merely slow and once it is gone for good, so a consumer that needs to
+    /// stop at the end of the stream has to check
+    /// [`is_terminated`](Self::is_terminated) as well.
+    pub fn try_recv_or_default(&mut self) -> T
+    where
+        T: Default,
+    {
+        self.try_recv().unwrap_or_default()
+    }
+
+    /// Moves the front element straight into `dst` and returns whether there
+    /// was one.
+    ///
+    /// `dst` is left untouched when the queue is empty, so whatever it held
+    /// before, initialized or not, is still there.
+    pub fn try_recv_into(&mut self, dst: &mut MaybeUninit<T>) -> bool {
+        let Ok(tail) = self.front() else {
+            return false;
+        };
+        let shared = unsafe { self.ptr.as_ref() };
+        unsafe { ptr::copy_nonoverlapping(shared.slot(tail).cast::<T>(), dst.as_mut_ptr(), 1) };
+        self.pop_front(tail, 1);
+        true
+    }
+
+    // Returns the position of the front element, if there is one, moving on
+    // to the sender's new buffer first if it went over to one.
+    fn front(&mut self) -> Result<usize, TryRecvError> {
+        loop {
+            let shared = unsafe { self.ptr.as_ref() };
+            let tail = shared.meta.tail.load(Ordering::Relaxed);
+            let mut head = shared.meta.head.load(Ordering::Acquire);
+            // If tail == head, then the queue is empty.
+            if tail == head {
+                let state = shared.meta.state.load(Ordering::Acquire);
+                // A sender that moved over doesn't set its bit again here.
+                if state & MOVED != 0 {
+                    self.move_on();
+                    continue;
+                }
+                if state & TX != 0 {
+                    return Err(TryRecvError::Empty);
+                }
+                // The sender may have pushed right before it dropped, so look
+                // at `head` again now that its final store is visible.
+                head = shared.meta.head.load(Ordering::Acquire);
+                if tail == head {
+                    return Err(TryRecvError::Disconnected);
+                }
+            }
+            return Ok(tail);
+        }
+    }
+
+    // Moves on to the buffer the sender went over to, if it did. Every
+    // receive checks first, and nothing may keep a reference to the old
+    // buffer across it.
+    #[inline]
+    fn catch_up(&mut self) {
+        let shared = unsafe { self.ptr.as_ref() };
+        if shared.meta.state.load(Ordering::Relaxed) & MOVED != 0 {
+            self.move_on();
+        }
+    }
+
+    #[cold]
+    fn move_on(&mut self) {
+        loop {
+            let old_ptr = self.ptr;
+            let old = unsafe { old_ptr.as_ref() };
+            if old.meta.state.load(Ordering::Acquire) & MOVED == 0 {
+                return;
+            }
+            // The sender only moved once we had taken everything, so there
+            // is nothing left to drop here.
+            self.ptr = unsafe { old.moved_to() };
+            #[cfg(feature = "metrics")]
+            {
+                let received = old.meta.total_received.load(Ordering::Relaxed);
+                let new = unsafe { self.ptr.as_ref() };
+                new.meta
+                    .total_received
+                    .fetch_add(received, Ordering::Relaxed);
+            }
+            if old.release(RX, RX_WAKING, TX, &old.meta.tx_waker) {
+                unsafe { Shared::free(old_ptr) };
+            }
+        }
+    }
+
+    // Returns the buffer the sender is writing to, along with its state,
+    // without moving on to it, for the methods that only look.
+    fn view(&self) -> (&Shared<T, A>, usize) {
+        let mut shared = unsafe { self.ptr.as_ref() };
+        loop {
+            let state = shared.meta.state.load(Ordering::Acquire);
+            if state & MOVED == 0 {
+                return (shared, state);
+            }
+            // We still hold every buffer up to the sender's.
+            shared = unsafe { shared.moved_to().as_ref() };
+        }
+    }
+
+    // Like `len`, but for the buffer we are on, which is the one to go by
+    // before touching slots.
+    fn queued(&self) -> usize {
+        let shared = unsafe { self.ptr.as_ref() };
+        let tail = shared.meta.tail.load(Ordering::Relaxed);
+        let head = shared.meta.head.load(Ordering::Acquire);
+        shared.len(head, tail)
+    }
+
+    // Hands the `n` slots from `tail` back to the sender. The values must
+    // already be out of them.
+    fn pop_front(&mut self, tail: usize, n: usize) {
+        let shared = unsafe { self.ptr.as_ref() };
+        self.pop_front_to(shared.advance(tail, n), n);
+    }
+
+    // Like `pop_front`, with the new tail already worked out.
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    fn pop_front_to(&mut self, new_tail: usize, n: usize) {
+        let shared = unsafe { self.ptr.as_ref() };
+        shared.meta.tail.store(new_tail, Ordering::Release);
+        #[cfg(feature = "metrics")]
+        shared.meta.total_received.fetch_add(n, Ordering::Relaxed);
+        #[cfg(all(feature = "metrics", feature = "std"))]
+        self.log.record(n);
+        #[cfg(feature = "heartbeat")]
+        shared.beat();
+        shared.meta.tx_waker.wake();
+    }
+
+    /// Receives up to `max` elements, fewer if the queue runs empty first.
+    pub fn recv_many(&mut self, max: usize) -> Vec<T> {
+        self.catch_up();
+        let shared = unsafe { self.ptr.as_ref() };
+        let tail = shared.meta.tail.load(Ordering::Relaxed);
+        let head = shared.meta.head.load(Ordering::Acquire);
+        let n = max.min(shared.distance(head, tail));
+        let mut out = Vec::with_capacity(n);
+        for i in 0..n {
+            let el = unsafe { (*shared.slot(shared.advance(tail, i))).assume_init_read() };
+            out.push(el);
+        }
+        if n > 0 {
+            self.pop_front(tail, n);
+        }
+        out
+    }
+
+    /// Receives elements from the front for as long as `pred` accepts them.
+    ///
+    /// The first element `pred` turns down stays queued, along with
+    /// everything behind it, so this only ever takes a prefix of the queue;
+    /// a ring gives up its elements front first. Elements sent once this
+    /// has started are left for later.
+    pub fn drain_filter(&mut self, mut pred: impl FnMut(&T) -> bool) -> Vec<T> {
+        self.catch_up();
+        let shared = unsafe { self.ptr.as_ref() };
+        let tail = shared.meta.tail.load(Ordering::Relaxed);
+        let head = shared.meta.head.load(Ordering::Acquire);
+        // Look at them all before taking any, so a panicking `pred` leaves
+        // the queue as it was.
+        let n = (0..shared.distance(head, tail))
+            .take_while(|&i| {
+                pred(unsafe { (*shared.slot(shared.advance(tail, i))).assume_init_ref() })
+            })
+            .count();
+        let out = (0..n)
+            .map(|i| unsafe { (*shared.slot(shared.advance(tail, i))).assume_init_read() })
+            .collect();
+        if n > 0 {
+            self.pop_front(tail, n);
+        }
+        out
+    }
+
+    /// Takes every element queued right now in one go.
+    ///
+    /// The elements are copied out in at most two blocks and the sender gets
+    /// all of the room back with a single update, so it can keep filling the
+    /// queue while the caller works through the batch.
+    pub fn take_all(&mut self) -> Vec<T> {
+        self.catch_up();
+        let shared = unsafe { self.ptr.as_ref() };
+        let cap = shared.buffer.len();
+        let tail = shared.meta.tail.load(Ordering::Relaxed);
+        let head = shared.meta.head.load(Ordering::Acquire);
+        let n = shared.distance(head, tail);
+        let mut out = Vec::with_capacity(n);
+        if n == 0 {
+            return out;
+        }
+        let start = shared.index(tail);
+        let first = n.min(cap - start);
+        let src = shared.slots();
+        unsafe {
+            core::ptr::copy_nonoverlapping(src.add(start), out.as_mut_ptr(), first);
+            core::ptr::copy_nonoverlapping(src, out.as_mut_ptr().add(first), n - first);
+            out.set_len(n);
+        }
+        self.pop_front(tail, n);
+        out
+    }
+
+    /// Moves as many queued elements as fit into the front of `dst` and
+    /// returns how many were received.
+    pub fn try_recv_slice(&mut self, dst: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        self.catch_up();
+        let shared = unsafe { self.ptr.as_ref() };
+        let cap = shared.buffer.len();
+        let tail = shared.meta.tail.load(Ordering::Relaxed);
+        let head = shared.meta.head.load(Ordering::Acquire);
+        let n = dst.len().min(shared.distance(head, tail));
+        if n == 0 {
+            return 0;
+        }
+        // Drain up to the end of the buffer, then wrap around to the start.
+        let start = shared.index(tail);
+        let first = n.min(cap - start);
+        let src = shared.slots();
+        unsafe {
+            core::ptr::copy_nonoverlapping(src.add(start), dst.as_mut_ptr(), first);
+            core::ptr::copy_nonoverlapping(src, dst.as_mut_ptr().add(first), n - first);
+        }
+        self.pop_front(tail, n);
+        n
+    }
+
+    /// Returns the element that the next `try_recv` would return, without
+    /// removing it.
+    pub fn peek(&self) -> Option<&T> {
+        let (shared, _) = self.view();
+        let tail = shared.meta.tail.load(Ordering::Relaxed);
+        let head = shared.meta.head.load(Ordering::Acquire);
+        // The slot at `tail` belongs to us until `tail` moves, and that needs
+        // `&mut self`.
+        (tail != head).then(|| unsafe { (*shared.slot(tail)).assume_init_ref() })
+    }
+
+    /// Borrows up to `max` elements from the front of the queue without
+    /// receiving them.
+    ///
+    /// Only the elements before the end of the buffer are included, so this
+    /// may return fewer than are queued; see [`as_slices`](Self::as_slices)
+    /// for the rest. Use [`skip`](Self::skip) to drop them once inspected.
+    pub fn peek_slice(&self, max: usize) -> &[T] {
+        let (front, _) = self.as_slices();
+        &front[..max.min(front.len())]
+    }
+
+    /// Clones the queued elements into a `Vec`, front first, leaving them in
+    /// the queue.
+    ///
+    /// The sender may keep adding elements meanwhile; the copy holds the ones
+    /// that were queued when it started.
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let (front, back) = self.as_slices();
+        let mut out = Vec::with_capacity(front.len() + back.len());
+        out.extend_from_slice(front);
+        out.extend_from_slice(back);
+        out
+    }
+
+    /// Returns the queued elements as up to two slices, front first, the way
+    /// [`VecDeque::as_slices`](alloc::collections::VecDeque::as_slices) does.
+    ///
+    /// The sender only ever appends past them, so the slices stay valid until
+    /// the next element is received. For the same reason there is no
+    /// `make_contiguous`: rotating the elements would move slots the sender
+    /// is writing without telling it.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let (shared, _) = self.view();
+        let tail = shared.meta.tail.load(Ordering::Relaxed);
+        let head = shared.meta.head.load(Ordering::Acquire);
+        let n = shared.distance(head, tail);
+        let start = shared.index(tail);
+        let first = n.min(shared.buffer.len() - start);
+        let base = shared.slots();
+        unsafe {
+            (
+                core::slice::from_raw_parts(base.add(start), first),
+                core::slice::from_raw_parts(base, n - first),
+            )
+        }
+    }
+
+    /// Borrows the element at the front of the queue, or returns `None` if the
+    /// queue is empty.
+    ///
+    /// The element stays queued unless the guard is consumed with
+    /// [`ReadGuard::take`] or [`ReadGuard::commit`].
+    pub fn recv_ref(&mut self) -> Option<ReadGuard<'_, T, A>> {
+        self.catch_up();
+        let shared = unsafe { self.ptr.as_ref() };
+        let tail = shared.meta.tail.load(Ordering::Relaxed);
+        let head = shared.meta.head.load(Ordering::Acquire);
+        (tail != head).then_some(ReadGuard {
+            receiver: self,
+            tail,
+        })
+    }
+
+    /// Drops every element currently in the queue.
+    pub fn clear(&mut self) {
+        self.catch_up();
+        self.drop_front(self.queued());
+    }
+
+    /// Drops the first `n` queued elements, for example once
+    /// [`peek_slice`](Self::peek_slice) showed they were a header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if fewer than `n` elements are queued.
+    pub fn skip(&mut self, n: usize) {
+        self.catch_up();
+        assert!(n <= self.queued(), "skipping more elements than are queued");
+        self.drop_front(n);
+    }
+
+    // Drops the first `n` elements, which must be queued.
+    fn drop_front(&mut self, n: usize) {
+        // Hands the slots back in one go, including when a destructor panics
+        // partway through.
+        struct Release<'a, T, A: Allocator> {
+            receiver: &'a mut Receiver<T, A>,
+            pos: usize,
+            dropped: usize,
+        }
+
+        impl<T, A: Allocator> Drop for Release<'_, T, A> {
+            fn drop(&mut self) {
+                self.receiver.pop_front_to(self.pos, self.dropped);
+            }
+        }
+
+        if n == 0 {
+            return;
+        }
+        let shared = unsafe { self.ptr.as_ref() };
+        let mut release = Release {
+            pos: shared.meta.tail.load(Ordering::Relaxed),
+            receiver: self,
+            dropped: 0,
+        };
+        while release.dropped < n {
+            let slot = shared.slot(release.pos);
+            release.pos = shared.advance(release.pos, 1);
+            release.dropped += 1;
+            unsafe { (*slot).assume_init_drop() };
+        }
+    }
+
+    /// Tries to receive an element, registering `cx`'s waker to be woken once
+    /// one arrives if the queue is empty.
+    ///
+    /// Returns `Ready(None)` once the queue is empty and the sender is gone.
+    #[cfg(feature = "async")]
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        // The buffer whose slot holds our waker. See `Sender::poll_send`.
+        let mut registered = None;
+        loop {
+            match self.try_recv() {
+                Ok(el) => return Poll::Ready(Some(el)),
+                Err(TryRecvError::Disconnected) => return Poll::Ready(None),
+                Err(TryRecvError::Empty) if registered == Some(self.ptr) => {
+                    return Poll::Pending;
+                }
+                Err(TryRecvError::Empty) => {}
+            }
+            // Check once more after registering so a send that landed in
+            // between isn't missed.
+            unsafe { self.ptr.as_ref() }
+                .meta
+                .rx_waker
+                .register(cx.waker());
+            registered = Some(self.ptr);
+        }
+    }
+
+    /// Returns an iterator over the elements currently in the queue.
+    ///
+    /// The iterator stops as soon as the queue is empty; it never blocks.
+    pub fn try_iter(&mut self) -> TryIter<'_, T, A> {
+        TryIter { rx: self }
+    }
+
+    /// Returns an iterator that polls the queue without blocking, yielding
+    /// `Err(TryRecvError::Empty)` whenever there is nothing to take yet.
+    ///
+    /// This lets a cooperative scheduler do other work between polls. The
+    /// iterator only ends once the queue is empty and the sender is gone, so
+    /// a caller that never backs off on `Empty` spins.
+    pub fn iter_with_errors(&mut self) -> PollIter<'_, T, A> {
+        PollIter { rx: self }
+    }
+
+    /// Returns an iterator over batches of up to `n` queued elements.
+    ///
+    /// Each batch is taken like [`recv_many`](Self::recv_many), with a single
+    /// update handing the room back to the sender. The iterator stops as
+    /// soon as the queue is empty, so the last batch may be shorter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn chunks(&mut self, n: usize) -> Chunks<'_, T, A> {
+        assert!(n > 0, "chunk size must be greater than zero");
+        Chunks { rx: self, n }
+    }
+
+    /// Returns an iterator that blocks for each element and ends once the
+    /// sender is gone and the queue has been drained.
+    ///
+    /// Unlike [`into_iter`](IntoIterator::into_iter), it borrows the receiver
+    /// and can [`peek`](BlockingIter::peek) ahead.
+    #[cfg(feature = "std")]
+    pub fn blocking_iter(&mut self) -> BlockingIter<'_, T, A> {
+        BlockingIter { rx: self }
+    }
+
+    /// Returns an iterator that takes exactly the elements queued right now.
+    ///
+    /// Elements sent after this call stay queued. Whatever the iterator
+    /// hasn't yielded when it is dropped is dropped along with it.
+    pub fn drain(&mut self) -> Drain<'_, T, A> {
+        let remaining = self.len();
+        Drain {
+            rx: self,
+            remaining,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    /// Receives an element, blocking while the queue is empty.
+    ///
+    /// Fails once the queue is empty and the sender is gone.
+    pub fn recv(&mut self) -> Result<T, RecvError> {
+        self.recv_deadline(None, None).map_err(|_| RecvError)
+    }
+
+    #[cfg(feature = "std")]
+    /// Receives an element like [`recv`](Self::recv), but polls the queue up
+    /// to `spins` times before parking.
+    ///
+    /// Spinning trades CPU time for a quicker handoff when the sender is
+    /// expected to push soon.
+    pub fn recv_spin(&mut self, spins: u32) -> Result<T, RecvError> {
+        self.recv_deadline(Some(spins), None).map_err(|_| RecvError)
+    }
+
+    #[cfg(feature = "std")]
+    /// Receives an element, blocking for at most `dur` while the queue is
+    /// empty.
+    pub fn recv_timeout(&mut self, dur: Duration) -> Result<T, RecvTimeoutError> {
+        self.recv_deadline(None, Instant::now().checked_add(dur))
+    }
+
+    #[cfg(feature = "std")]
+    /// Receives up to `max` elements, blocking until it has them all or
+    /// `deadline` passes, whichever comes first.
+    ///
+    /// Returns whatever arrived by then, which may be nothing. Also returns
+    /// early once the queue is empty and the sender is gone.
+    pub fn recv_batch_until(&mut self, max: usize, deadline: Instant) -> Vec<T> {
+        let mut out = Vec::new();
+        while out.len() < max {
+            // Take what is already there in one go before blocking again.
+            let more = self.recv_many(max - out.len());
+            if !more.is_empty() {
+                out.extend(more);
+                continue;
+            }
+            match self.recv_deadline(None, Some(deadline)) {
+                Ok(el) => out.push(el),
+                Err(_) => break,
+            }
+        }
+        out
+    }
+
+    #[cfg(feature = "std")]
+    /// Blocks for one element, then takes whatever else is already queued,
+    /// up to the capacity.
+    ///
+    /// Batches stay small while the sender is slow and grow as it speeds
+    /// up, so a lightly loaded queue keeps its latency and a busy one its
+    /// throughput. Returns an empty batch once the queue is empty and the
+    /// sender is gone.
+    pub fn recv_adaptive(&mut self) -> Vec<T> {
+        let Ok(first) = self.recv() else {
+            return Vec::new();
+        };
+        let cap = self.capacity();
+        let mut out = Vec::with_capacity(cap);
+        out.push(first);
+        // The sender may keep adding while we drain.
+        while out.len() < cap {
+            let more = self.recv_many(cap - out.len());
+            if more.is_empty() {
+                break;
+            }
+            out.extend(more);
+        }
+        out
+    }
+
+    #[cfg(feature = "std")]
+    /// Blocks until there is at least one element, without taking it.
+    ///
+    /// Afterwards the element can be looked at with [`peek`](Self::peek) or
+    /// [`as_slices`](Self::as_slices), or taken in whichever way suits. Fails
+    /// once the queue is empty and the sender is gone.
+    pub fn wait_nonempty(&mut self) -> Result<(), RecvError> {
+        self.block_on(None, None, |rx| match rx.front() {
+            Ok(_) => Some(Ok(())),
+            Err(TryRecvError::Disconnected) => Some(Err(RecvError)),
+            Err(TryRecvError::Empty) => None,
+        })
+        .unwrap_or(Err(RecvError))
+    }
+
+    #[cfg(feature = "std")]
+    fn recv_deadline(
+        &mut self,
+        spins: Option<u32>,
+        deadline: Option<Instant>,
+    ) -> Result<T, RecvTimeoutError> {
+        self.block_on(spins, deadline, |rx| match rx.try_recv() {
+            Ok(el) => Some(Ok(el)),
+            Err(TryRecvError::Disconnected) => Some(Err(RecvTimeoutError::Disconnected)),
+            Err(TryRecvError::Empty) => None,
+        })
+        .unwrap_or(Err(RecvTimeoutError::Timeout))
+    }
+
+    // `waker::block_on` on our waker slot, for an `op` that receives. See
+    // `Sender::block_on`.
+    #[cfg(feature = "std")]
+    fn block_on<R>(
+        &mut self,
+        spins: Option<u32>,
+        deadline: Option<Instant>,
+        mut op: impl FnMut(&mut Self) -> Option<R>,
+    ) -> Option<R> {
+        loop {
+            let ptr = self.ptr;
+            let slot = unsafe { &ptr.as_ref().meta.rx_waker };
+            let res = waker::block_on(&[slot], spins, deadline, || match op(self) {
+                Some(r) => Some(Some(r)),
+                None if self.ptr.cast::<()>() != ptr.cast() => Some(None),
+                None => None,
+            });
+            match res {
+                Some(None) => {}
+                res => return res.flatten(),
+            }
+        }
+    }
+
+    /// Returns the number of elements the queue can hold.
+    ///
+    /// After [`shrink_to`](Self::shrink_to), this stays at the old capacity
+    /// until the receiver itself moves on to the new buffer, which it does
+    /// with the first receive after the sender went over.
+    pub const fn capacity(&self) -> usize {
+        unsafe { self.ptr.as_ref() }.buffer.len()
+    }
+
+    /// Moves the queue to a buffer of `new_cap` slots, for handing back
+    /// memory once a burst has passed.
+    ///
+    /// The new buffer is allocated here and left for the sender, which moves
+    /// over the next time it writes to an empty queue. Until then it keeps
+    /// sending into the old buffer, so elements already queued stay where
+    /// they are and are received first, and `new_cap` may be below the
+    /// current length. Positions and acknowledgements carry on across the
+    /// move. Each end reports the new [`capacity`](Self::capacity) once it
+    /// is on the new buffer, and the old one is freed once both are.
+    ///
+    /// Only one move is pending at a time; asking again before the sender
+    /// went over does nothing, as does asking once the sender is gone.
+    /// Nothing stops `new_cap` from being larger, though the queue only
+    /// grows once it has been emptied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `new_cap` is zero, if the queue came from [`new_const`] or
+    /// [`new_typed`], or if the buffer would take up more than `isize::MAX`
+    /// bytes. Allocation failure is reported through [`handle_alloc_error`].
+    pub fn shrink_to(&mut self, new_cap: usize)
+    where
+        A: Clone,
+    {
+        assert!(new_cap > 0, "queue capacity must be greater than zero");
+        self.catch_up();
+        let (end, state) = self.view();
+        assert!(
+            state & FIXED == 0,
+            "the queue's capacity is part of its handles' type"
+        );
+        // Only the receiver stores `next`, so this isn't stale.
+        if state & TX == 0 || !end.meta.next.load(Ordering::Relaxed).is_null() {
+            return;
+        }
+        let layout = layout::<T, A>(new_cap).expect("capacity overflow");
+        let alloc = A::clone(&end.alloc);
+        let new = match alloc.allocate(layout) {
+            Ok(ptr) => unsafe { init_shared::<T, A>(ptr.cast(), new_cap, alloc) },
+            Err(_) => handle_alloc_error(layout),
+        };
+        #[cfg(feature = "heartbeat")]
+        unsafe {
+            // Nobody else sees the new buffer yet.
+            (*new.as_ptr()).meta.epoch = end.meta.epoch;
+            let last = end.meta.last_active.load(Ordering::Relaxed);
+            new.as_ref().meta.last_active.store(last, Ordering::Relaxed);
+        }
+        // The new buffer's `TX` is the sender's to let go of once it moves
+        // over, or the old buffer's to free if it never does.
+        end.meta.next.store(new.as_ptr().cast(), Ordering::Release);
+        end.meta.state.fetch_or(RESIZING, Ordering::Release);
+    }
+
+    /// Returns the number of elements currently in the queue.
+    ///
+    /// The sender may be pushing concurrently, so this is a lower bound.
+    pub fn len(&self) -> usize {
+        let (shared, _) = self.view();
+        let tail = shared.meta.tail.load(Ordering::Relaxed);
+        let head = shared.meta.head.load(Ordering::Acquire);
+        shared.len(head, tail)
+    }
+
+    /// Returns the number of elements like [`len`](Self::len), or `None` if
+    /// the queue's positions say more are queued than fit. See
+    /// [`Sender::len_checked`].
+    pub fn len_checked(&self) -> Option<usize> {
+        let (shared, _) = self.view();
+        let tail = shared.meta.tail.load(Ordering::Relaxed);
+        let head = shared.meta.head.load(Ordering::Acquire);
+        shared.checked_len(head, tail)
+    }
+
+    /// Panics if the queue's positions or handle bookkeeping don't add up.
+    /// See [`Sender::assert_invariants`].
+    #[cfg(any(test, feature = "debug-checks"))]
+    pub fn assert_invariants(&self) {
+        unsafe { self.ptr.as_ref() }.assert_invariants(RX);
+    }
+
+    /// Returns how long ago an element was last sent or received, or how
+    /// long the queue has existed if neither happened yet.
+    ///
+    /// Lets a watchdog notice a stalled producer or consumer.
+    #[cfg(feature = "heartbeat")]
+    pub fn idle_for(&self) -> Duration {
+        let meta = &self.view().0.meta;
+        let last = Duration::from_nanos(meta.last_active.load(Ordering::Relaxed));
+        meta.epoch.elapsed().saturating_sub(last)
+    }
+
+    /// Returns the queue's counters. See [`Sender::stats`].
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> Stats {
+        // Receives from buffers we haven't moved on from yet aren't carried
+        // over to the sender's.
+        let mut shared = unsafe { self.ptr.as_ref() };
+        let mut total_received = 0;
+        loop {
+            total_received += shared.meta.total_received.load(Ordering::Relaxed);
+            if shared.meta.state.load(Ordering::Acquire) & MOVED == 0 {
+                break;
+            }
+            shared = unsafe { shared.moved_to().as_ref() };
+        }
+        Stats {
+            total_received,
+            ..shared.stats()
+        }
+    }
+
+    /// Estimates how many elements per second were received over the last
+    /// `window`.
+    ///
+    /// Only the last few receives made through this handle are remembered,
+    /// so with a long window and a busy queue this covers a shorter stretch.
+    /// Returns zero until at least two receives fall within the window.
+    #[cfg(all(feature = "metrics", feature = "std"))]
+    pub fn throughput(&mut self, window: Duration) -> f64 {
+        self.log.rate(window)
+    }
+
+    /// Tells the sender that every element before position `upto` has been
+    /// processed, as opposed to just received.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `upto` is behind an earlier acknowledgement or past what was
+    /// received.
+    pub fn ack(&mut self, upto: usize) {
+        self.catch_up();
+        let mut shared = unsafe { self.ptr.as_ref() };
+        let acked = shared.meta.acked.load(Ordering::Relaxed);
+        let tail = shared.meta.tail.load(Ordering::Relaxed);
+        assert!(
+            shared.distance(upto, acked) <= shared.distance(tail, acked),
+            "acknowledged position out of range"
+        );
+        // Pairs with `Sender::resize`: if the sender copied `acked` over
+        // before this store, it has set `MOVING` by then, and the store goes
+        // to the new buffer too.
+        loop {
+            shared.meta.acked.store(upto, Ordering::SeqCst);
+            if shared.meta.state.load(Ordering::SeqCst) & MOVING == 0 {
+                break;
+            }
+            shared = unsafe { shared.moved_to().as_ref() };
+        }
+    }
+
+    /// Returns the raw `(head, tail)` counters. See
+    /// [`Sender::debug_positions`].
+    pub fn debug_positions(&self) -> (usize, usize) {
+        self.view().0.positions()
+    }
+
+    /// Returns a pointer to the first of the [`capacity`](Self::capacity)
+    /// slots backing the queue, for handing the storage to code that does
+    /// its own index math.
+    ///
+    /// The element at position `pos` of [`debug_positions`](Self::debug_positions)
+    /// lives in slot `pos % capacity()`. Only the positions from `tail` up to
+    /// `head` are initialized, and that range may wrap past the last slot
+    /// back to the first. Reading them is fine until they are received, but
+    /// the sender writes into every other slot concurrently, so touching any
+    /// of those is a data race. The pointer stays valid for as long as the
+    /// receiver is around, or until the queue moves to another buffer after
+    /// [`shrink_to`](Self::shrink_to).
+    pub fn as_ptr(&self) -> *const MaybeUninit<T> {
+        self.view().0.slots().cast_const().cast()
+    }
+
+    /// Returns `true` if the queue is empty. See [`Receiver::len`].
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the queue is full. See [`Receiver::len`].
+    pub fn is_full(&self) -> bool {
+        let (shared, _) = self.view();
+        self.len() == shared.buffer.len()
+    }
+
+    /// Returns `true` if the sender is still around.
+    ///
+    /// The sender may be dropped right after this returns, so treat `true` as
+    /// a hint. `false` is final, though elements sent before the drop may
+    /// still be queued.
+    pub fn is_connected(&self) -> bool {
+        self.view().1 & TX != 0
+    }
+
+    /// Returns `true` once the sender is gone and every element it sent has
+    /// been received, which is when [`try_recv`](Self::try_recv) starts
+    /// reporting [`Disconnected`](TryRecvError::Disconnected).
+    ///
+    /// Unlike [`is_connected`](Self::is_connected), `true` is final, so a
+    /// consumer loop can stop on it.
+    pub fn is_terminated(&self) -> bool {
+        let (shared, state) = self.view();
+        // Checked before `head`, so the `Acquire` makes the sender's last
+        // store to it visible.
+        state & TX == 0 && {
+            let (head, tail) = shared.positions();
+            head == tail
+        }
+    }
+
+    /// Returns `true` if the sender ended the stream with [`Sender::close`]
+    /// rather than being dropped.
+    ///
+    /// Check this once the queue reports being disconnected to tell a clean
+    /// end of stream from an abrupt one.
+    pub fn is_closed(&self) -> bool {
+        self.view().1 & CLOSED != 0
+    }
+
+    /// Returns `true` once the sender has asked to shut down with
+    /// [`Sender::begin_shutdown`].
+    ///
+    /// Elements may still be queued, and more may still arrive. Receive
+    /// whatever needs handling and then call
+    /// [`ack_shutdown`](Self::ack_shutdown).
+    pub fn is_draining(&self) -> bool {
+        self.view().1 & DRAINING != 0
+    }
+
+    /// Tells a sender waiting in [`Sender::finish_shutdown`] that the
+    /// receiver is done.
+    ///
+    /// Whatever is still queued at that point is dropped along with the
+    /// queue, so drain it first if it matters.
+    pub fn ack_shutdown(&mut self) {
+        let mut shared = unsafe { self.ptr.as_ref() };
+        // A sender that is moving over may not have carried the bit along.
+        while shared.meta.state.fetch_or(SHUTDOWN_ACKED, Ordering::AcqRel) & MOVING != 0 {
+            shared = unsafe { shared.moved_to().as_ref() };
+        }
+        shared.meta.tx_waker.wake();
+    }
+
+    /// Turns the receiver into a raw pointer, for example to hand it across an
+    /// FFI boundary.
+    ///
+    /// The receiver stays alive until it is rebuilt with
+    /// [`from_raw`](Self::from_raw) and dropped. With the `metrics` and
+    /// `std` features, the rebuilt receiver's `throughput` estimate starts
+    /// over.
+    pub fn into_raw(self) -> NonNull<()> {
+        let this = ManuallyDrop::new(self);
+        // Only the pointer makes it through, so the log goes now.
+        #[cfg(all(feature = "metrics", feature = "std"))]
+        drop(unsafe { ptr::read(&this.log) });
+        this.ptr.cast()
+    }
+
+    /// Rebuilds a receiver from a pointer returned by
+    /// [`into_raw`](Self::into_raw).
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Receiver::<T, A>::into_raw` and each pointer must
+    /// be rebuilt exactly once.
+    pub unsafe fn from_raw(ptr: NonNull<()>) -> Self {
+        Receiver::from_shared(unsafe { Shared::from_thin(ptr) })
+    }
+
+    /// Disconnects from the sender, dropping whatever is still queued.
+    ///
+    /// This is the same as dropping the receiver.
+    pub fn close(self) {}
+
+    /// Wraps the receiver so that whatever is still queued when it is
+    /// dropped is handed to `f`, front first, instead of just being dropped.
+    ///
+    /// Elements the sender pushes after the drain started are dropped as
+    /// usual.
+    pub fn with_drain_handler<F: FnMut(T)>(self, f: F) -> DrainOnDrop<T, F, A> {
+        DrainOnDrop { rx: self, f }
+    }
+
+    /// Wraps the receiver so that `f` is called with the current length each
+    /// time [`HighWater::try_recv`] finds more than `items` queued after
+    /// finding at most that many before.
+    ///
+    /// The callback runs on the receiving thread, so it can flag a consumer
+    /// falling behind without any synchronization of its own.
+    pub fn with_high_water<F: FnMut(usize)>(self, items: usize, f: F) -> HighWater<T, F, A> {
+        HighWater {
+            rx: self,
+            mark: items,
+            above: false,
+            f,
+        }
+    }
+}
+
+unsafe impl<T: Send, A: Allocator + Send> Send for Receiver<T, A> {}
+
+impl<T, A: Allocator> fmt::Debug for Receiver<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("capacity", &self.capacity())
+            .field("len", &self.len())
+            .field("connected", &self.is_connected())
+            .finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, A: Allocator> IntoIterator for Receiver<T, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+
+    fn into_iter(self) -> IntoIter<T, A> {
+        IntoIter {
+            rx: self,
+            done: false,
+        }
+    }
+}
+
+/// The front element of the queue, handed out by [`Receiver::recv_ref`].
+///
+/// Dropping the guard leaves the element at the front of the queue.
+#[derive(Debug)]
+pub struct ReadGuard<'a, T, A: Allocator = Global> {
+    receiver: &'a mut Receiver<T, A>,
+    tail: usize,
+}
+
+impl<T, A: Allocator> ReadGuard<'_, T, A> {
+    /// Removes the element from the queue and returns it.
+    pub fn take(self) -> T {
+        let shared = unsafe { self.receiver.ptr.as_ref() };
+        let el = unsafe { (*shared.slot(self.tail)).assume_init_read() };
+        self.release();
+        el
+    }
+
+    /// Removes the element from the queue and drops it.
+    pub fn commit(self) {
+        drop(self.take());
+    }
+
+    fn release(self) {
+        self.receiver.pop_front(self.tail, 1);
+    }
+}
+
+impl<T, A: Allocator> Deref for ReadGuard<'_, T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        let shared = unsafe { self.receiver.ptr.as_ref() };
+        // The sender can't touch the slot until `tail` moves past it.
+        unsafe { (*shared.slot(self.tail)).assume_init_ref() }
+    }
+}
+
+/// Blocking iterator returned by [`Receiver::into_iter`].
+///
+/// It ends once the sender is gone and the queue has been drained.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct IntoIter<T, A: Allocator = Global> {
+    rx: Receiver<T, A>,
+    // Set once `recv` failed, so later calls don't look at the queue again.
+    done: bool,
+}
+
+#[cfg(feature = "std")]
+impl<T, A: Allocator> Iterator for IntoIter<T, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.done {
+            return None;
+        }
+        let el = self.rx.recv().ok();
+        self.done = el.is_none();
+        el
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            return (0, Some(0));
+        }
+        (self.rx.len(), None)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, A: Allocator> FusedIterator for IntoIter<T, A> {}
+
+/// Blocking iterator returned by [`Receiver::blocking_iter`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct BlockingIter<'a, T, A: Allocator = Global> {
+    rx: &'a mut Receiver<T, A>,
+}
+
+#[cfg(feature = "std")]
+impl<T, A: Allocator> BlockingIter<'_, T, A> {
+    /// Borrows the next element without taking it, blocking until there is
+    /// one.
+    ///
+    /// Returns `None` once the sender is gone and the queue has been drained.
+    pub fn peek(&mut self) -> Option<&T> {
+        self.rx.wait_nonempty().ok()?;
+        self.rx.peek()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, A: Allocator> Iterator for BlockingIter<'_, T, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.rx.len(), None)
+    }
+}
+
+/// A receiver returned by [`Receiver::with_drain_handler`].
+///
+/// It derefs to the wrapped [`Receiver`].
+pub struct DrainOnDrop<T, F: FnMut(T), A: Allocator = Global> {
+    rx: Receiver<T, A>,
+    f: F,
+}
+
+impl<T, F: FnMut(T), A: Allocator> fmt::Debug for DrainOnDrop<T, F, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DrainOnDrop")
+            .field("rx", &self.rx)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, F: FnMut(T), A: Allocator> Deref for DrainOnDrop<T, F, A> {
+    type Target = Receiver<T, A>;
+
+    fn deref(&self) -> &Receiver<T, A> {
+        &self.rx
+    }
+}
+
+impl<T, F: FnMut(T), A: Allocator> DerefMut for DrainOnDrop<T, F, A> {
+    fn deref_mut(&mut self) -> &mut Receiver<T, A> {
+        &mut self.rx
+    }
+}
+
+impl<T, F: FnMut(T), A: Allocator> Drop for DrainOnDrop<T, F, A> {
+    fn drop(&mut self) {
+        // The receiver itself is dropped, and the queue freed, right after.
+        // A sender that keeps up could keep this going forever, so only take
+        // what is there now.
+        for _ in 0..self.rx.len() {
+            let Ok(el) = self.rx.try_recv() else { break };
+            (self.f)(el);
+        }
+    }
+}
+
+/// A receiver returned by [`Receiver::with_high_water`].
+///
+/// It derefs to the wrapped [`Receiver`]. Only its own
+/// [`try_recv`](Self::try_recv) checks the mark.
+pub struct HighWater<T, F: FnMut(usize), A: Allocator = Global> {
+    rx: Receiver<T, A>,
+    mark: usize,
+    // Whether the last check found the queue above the mark, so a backlog
+    // that stays high is reported once.
+    above: bool,
+    f: F,
+}
+
+impl<T, F: FnMut(usize), A: Allocator> HighWater<T, F, A> {
+    /// Receives an element like [`Receiver::try_recv`], calling the callback
+    /// first if the queue just went above the mark.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let len = self.rx.len();
+        let above = len > self.mark;
+        if above && !self.above {
+            (self.f)(len);
+        }
+        self.above = above;
+        self.rx.try_recv()
+    }
+
+    /// Unwraps the receiver, dropping the callback.
+    pub fn into_inner(self) -> Receiver<T, A> {
+        self.rx
+    }
+}
+
+impl<T, F: FnMut(usize), A: Allocator> fmt::Debug for HighWater<T, F, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HighWater")
+            .field("rx", &self.rx)
+            .field("mark", &self.mark)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, F: FnMut(usize), A: Allocator> Deref for HighWater<T, F, A> {
+    type Target = Receiver<T, A>;
+
+    fn deref(&self) -> &Receiver<T, A> {
+        &self.rx
+    }
+}
+
+impl<T, F: FnMut(usize), A: Allocator> DerefMut for HighWater<T, F, A> {
+    fn deref_mut(&mut self) -> &mut Receiver<T, A> {
+        &mut self.rx
+    }
+}
+
+/// Iterator returned by [`Receiver::try_iter`].
+#[derive(Debug)]
+pub struct TryIter<'a, T, A: Allocator = Global> {
+    rx: &'a mut Receiver<T, A>,
+}
+
+impl<T, A: Allocator> Iterator for TryIter<'_, T, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.try_recv().ok()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // The sender may add more while we iterate.
+        (self.rx.len(), None)
+    }
+}
+
+/// Iterator returned by [`Receiver::iter_with_errors`].
+#[derive(Debug)]
+pub struct PollIter<'a, T, A: Allocator = Global> {
+    rx: &'a mut Receiver<T, A>,
+}
+
+impl<T, A: Allocator> Iterator for PollIter<'_, T, A> {
+    type Item = Result<T, TryRecvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rx.try_recv() {
+            Err(TryRecvError::Disconnected) => None,
+            res => Some(res),
+        }
+    }
+}
+
+/// Iterator returned by [`Receiver::chunks`].
+#[derive(Debug)]
+pub struct Chunks<'a, T, A: Allocator = Global> {
+    rx: &'a mut Receiver<T, A>,
+    n: usize,
+}
+
+impl<T, A: Allocator> Iterator for Chunks<'_, T, A> {
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Vec<T>> {
+        let chunk = self.rx.recv_many(self.n);
+        (!chunk.is_empty()).then_some(chunk)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // The sender may add more while we iterate.
+        (self.rx.len().div_ceil(self.n), None)
+    }
+}
+
+/// Iterator returned by [`Receiver::drain`].
+#[derive(Debug)]
+pub struct Drain<'a, T, A: Allocator = Global> {
+    rx: &'a mut Receiver<T, A>,
+    remaining: usize,
+}
+
+impl<T, A: Allocator> Iterator for Drain<'_, T, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        // Only we take elements out, so everything counted is still there.
+        self.rx.try_recv().ok()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T, A: Allocator> ExactSizeIterator for Drain<'_, T, A> {}
+
+impl<T, A: Allocator> Drop for Drain<'_, T, A> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T, A: Allocator> Stream for Receiver<T, A> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+/// Lets the sender feed combinators like `forward` and `SinkExt::send`.
+///
+/// `poll_ready` waits for a free slot, which only the sender can fill, so
+/// the element passed to `start_send` right after always fits.
+///
+/// Closing makes `poll_ready` fail from then on, and the stream is reported
+/// as [closed](Receiver::is_closed) once the sender is dropped, same as after
+/// [`Sender::close`]. Until then the receiver sees nothing of it, and the
+/// sender's own methods can still send.
+#[cfg(feature = "async")]
+impl<T, A: Allocator> Sink<T> for Sender<T, A> {
+    type Error = SendError<()>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        let mut registered = false;
+        loop {
+            let state = unsafe { this.ptr.as_ref() }
+                .meta
+                .state
+                .load(Ordering::Relaxed);
+            if state & CLOSING != 0 || !this.is_connected() {
+                return Poll::Ready(Err(SendError(())));
+            }
+            if this.spare_capacity() > 0 {
+                return Poll::Ready(Ok(()));
+            }
+            if registered {
+                return Poll::Pending;
+            }
+            // Check once more after registering so a slot freed in between
+            // isn't missed.
+            unsafe { this.ptr.as_ref() }
+                .meta
+                .tx_waker
+                .register(cx.waker());
+            registered = true;
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, el: T) -> Result<(), Self::Error> {
+        // Skips the disconnect check, so an element accepted by `poll_ready`
+        // is never handed back; if the receiver is gone it is dropped along
+        // with the queue.
+        self.get_mut()
+            .reserve()
+            .expect("start_send called without poll_ready")
+            .write(el);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Sent elements are visible to the receiver right away.
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        unsafe { self.ptr.as_ref() }
+            .meta
+            .state
+            .fetch_or(CLOSING, Ordering::Relaxed);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Makes the queue usable as an in-memory pipe.
+///
+/// A full queue is reported as [`WouldBlock`](io::ErrorKind::WouldBlock), and
+/// a gone receiver as [`BrokenPipe`](io::ErrorKind::BrokenPipe).
+#[cfg(feature = "std")]
+impl<A: Allocator> io::Write for Sender<u8, A> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if !self.is_connected() {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        match self.try_send_slice(buf) {
+            0 => Err(io::ErrorKind::WouldBlock.into()),
+            n => Ok(n),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Ok(0);
+        }
+        if !self.is_connected() {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        // Each slice is copied straight into the slots, stopping at the
+        // first one that doesn't fit whole.
+        let mut written = 0;
+        for buf in bufs {
+            let n = self.try_send_slice(buf);
+            written += n;
+            if n < buf.len() {
+                break;
+            }
+        }
+        match written {
+            0 => Err(io::ErrorKind::WouldBlock.into()),
+            n => Ok(n),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads what the [`Sender`] wrote. An empty queue is reported as
+/// [`WouldBlock`](io::ErrorKind::WouldBlock) while the sender is around, and
+/// as the end of the stream once it is gone.
+#[cfg(feature = "std")]
+impl<A: Allocator> io::Read for Receiver<u8, A> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match self.try_recv_slice(buf) {
+            0 if self.is_connected() => Err(io::ErrorKind::WouldBlock.into()),
+            // The sender may have written right before it dropped.
+            0 => Ok(self.try_recv_slice(buf)),
+            n => Ok(n),
+        }
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Ok(0);
+        }
+        let mut fill = |rx: &mut Self| {
+            let mut read = 0;
+            for buf in bufs.iter_mut() {
+                let n = rx.try_recv_slice(buf);
+                read += n;
+                if n < buf.len() {
+                    break;
+                }
+            }
+            read
+        };
+        match fill(self) {
+            0 if self.is_connected() => Err(io::ErrorKind::WouldBlock.into()),
+            // The sender may have written right before it dropped.
+            0 => Ok(fill(self)),
+            n => Ok(n),
+        }
+    }
+}
+
+impl<T, A: Allocator> Drop for Receiver<T, A> {
+    fn drop(&mut self) {
+        // We hold every buffer from ours up to the sender's.
+        let mut ptr = self.ptr;
+        loop {
+            let shared = unsafe { ptr.as_ref() };
+            let state = shared.meta.state.load(Ordering::Acquire);
+            let next = NonNull::new(shared.meta.next.load(Ordering::Acquire));
+            let moved = state & MOVED != 0;
+            if let Some(next) = next.filter(|_| !moved) {
+                // The sender may be moving over to this one right now, so let
+                // go of it while the buffer it is moving from keeps both
+                // alive. If it never comes over, its bit here stays set and
+                // this goes with the buffer below.
+                let pending_ptr = unsafe { Shared::<T, A>::from_thin(next) };
+                let pending = unsafe { pending_ptr.as_ref() };
+                if pending.release(RX, RX_WAKING, TX, &pending.meta.tx_waker) {
+                    // It came over, and is gone already.
+                    unsafe { Shared::free(pending_ptr) };
+                }
+            }
+            let next = if moved {
+                Some(unsafe { shared.moved_to() })
+            } else {
+                None
+            };
+            if shared.release(RX, RX_WAKING, TX, &shared.meta.tx_waker) {
+                unsafe { Shared::free(ptr) };
+            }
+            match next {
+                Some(next) => ptr = next,
+                None => return,
+            }
+        }
+    }
+}
+
+/// Both ends of a queue in one value, to be split where they are put to
+/// use, for example when spawning the producer and consumer.
+#[derive(Debug)]
+pub struct Channel<T, A: Allocator = Global> {
+    tx: Sender<T, A>,
+    rx: Receiver<T, A>,
+}
+
+impl<T> Channel<T> {
+    /// Creates a queue that can hold up to `cap` elements, like [`new`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cap` is zero.
+    pub fn new(cap: usize) -> Self {
+        new(cap).into()
+    }
+}
+
+impl<T, A: Allocator> Channel<T, A> {
+    pub fn split(self) -> (Sender<T, A>, Receiver<T, A>) {
+        (self.tx, self.rx)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.tx.capacity()
+    }
+
+    /// Returns the number of elements in the queue.
+    ///
+    /// Both ends are still here, so nothing can change it behind our back.
+    pub fn len(&self) -> usize {
+        self.rx.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, A: Allocator> From<(Sender<T, A>, Receiver<T, A>)> for Channel<T, A> {
+    /// Puts the two ends of a queue back together.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tx` and `rx` don't belong to the same queue.
+    fn from((tx, mut rx): (Sender<T, A>, Receiver<T, A>)) -> Self {
+        // The receiver may not have followed the sender to a new buffer yet.
+        rx.catch_up();
+        assert_eq!(
+            tx.ptr.cast::<()>(),
+            rx.ptr.cast::<()>(),
+            "sender and receiver belong to different queues"
+        );
+        Channel { tx, rx }
+    }
+}
+
+/// Creates a queue that can hold up to `cap` elements.
+///
+/// Elements don't have to be `'static`. A queue of `&'a U` can carry
+/// borrowed data between threads spawned with [`std::thread::scope`], as
+/// long as `U` is `Sync` and the data outlives the scope; the borrow checker
+/// keeps both ends from escaping it.
+///
+/// # Panics
+///
+/// Panics if `cap` is zero.
+pub fn new<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    new_in(cap, Global)
+}
+
+/// Creates a queue that can hold up to `cap` elements, allocated with `alloc`.
+///
+/// # Panics
+///
+/// Panics if `cap` is zero or the queue would take up more than `isize::MAX`
+/// bytes. Allocation failure is reported through [`handle_alloc_error`].
+pub fn new_in<T, A: Allocator>(cap: usize, alloc: A) -> (Sender<T, A>, Receiver<T, A>) {
+    assert!(cap > 0, "queue capacity must be greater than zero");
+    let layout = layout::<T, A>(cap).expect("capacity overflow");
+    match alloc.allocate(layout) {
+        Ok(ptr) => unsafe { init(ptr.cast(), cap, alloc) },
+        Err(_) => handle_alloc_error(layout),
+    }
+}
+
+/// Both ends of a fresh queue.
+type Pair<T, A = Global> = (Sender<T, A>, Receiver<T, A>);
+
+/// Creates a queue that can hold up to `cap` elements, or fails if it can't
+/// be allocated.
+///
+/// # Panics
+///
+/// Panics if `cap` is zero.
+pub fn try_new<T>(cap: usize) -> Result<Pair<T>, AllocError> {
+    try_new_in(cap, Global)
+}
+
+/// Creates a queue that can hold up to `cap` elements, allocated with
+/// `alloc`, or fails if it can't be allocated.
+///
+/// This covers both `alloc` failing and a `cap` so large that the queue
+/// couldn't be described by a [`Layout`].
+///
+/// # Panics
+///
+/// Panics if `cap` is zero.
+pub fn try_new_in<T, A: Allocator>(cap: usize, alloc: A) -> Result<Pair<T, A>, AllocError> {
+    assert!(cap > 0, "queue capacity must be greater than zero");
+    let layout = layout::<T, A>(cap).map_err(|_| AllocError)?;
+    let ptr = alloc.allocate(layout)?;
+    Ok(unsafe { init(ptr.cast(), cap, alloc) })
+}
+
+/// Creates a queue that can hold up to `cap` elements inside `storage`.
+///
+/// Nothing is allocated on the heap, and both ends borrow `storage`, so it
+/// can live on the stack and the ends be handed to scoped threads. Use
+/// [`Scoped::size_for`] to find out how big `storage` must be.
+///
+/// `storage` is raw bytes rather than slots for `T` because the queue's
+/// header, with the positions and wakers both ends share, goes in front of
+/// the slots in the same buffer. To build a simpler queue over slots you
+/// already have, see [`const_spsc::new_in_buffer`](crate::const_spsc::new_in_buffer).
+///
+/// # Panics
+///
+/// Panics if `cap` is zero or the queue doesn't fit in `storage`.
+pub fn scoped<T>(cap: usize, storage: &mut [MaybeUninit<u8>]) -> Pair<T, Scoped<'_>> {
+    try_new_in(cap, Scoped::new(storage)).expect("storage too small for the queue")
+}
+
+/// An allocator that hands out a borrowed buffer, once.
+///
+/// Queues allocated with it, through [`scoped`] or [`new_in`], can't outlive
+/// the buffer. Freeing does nothing; the buffer is just left for its owner
+/// to reuse.
+pub struct Scoped<'a> {
+    storage: NonNull<[MaybeUninit<u8>]>,
+    taken: Cell<bool>,
+    _marker: PhantomData<&'a mut [MaybeUninit<u8>]>,
+}
+
+// SAFETY: `Scoped` is a unique borrow of the buffer. The `Cell` is only
+// touched through `&self` by `allocate`, and queues never share their
+// allocator between threads.
+unsafe impl Send for Scoped<'_> {}
+
+impl<'a> Scoped<'a> {
+    pub fn new(storage: &'a mut [MaybeUninit<u8>]) -> Self {
+        Scoped {
+            storage: NonNull::from(storage),
+            taken: Cell::new(false),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns how many bytes of storage a queue of `cap` elements needs,
+    /// wherever the buffer happens to start.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue would take up more than `isize::MAX` bytes.
+    pub fn size_for<T>(cap: usize) -> usize {
+        let layout = layout::<T, Scoped<'_>>(cap).expect("capacity overflow");
+        layout.size() + layout.align() - 1
+    }
+}
+
+impl fmt::Debug for Scoped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scoped")
+            .field("len", &self.storage.len())
+            .field("taken", &self.taken.get())
+            .finish()
+    }
+}
+
+unsafe impl Allocator for Scoped<'_> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if self.taken.get() {
+            return Err(AllocError);
+        }
+        let start = self.storage.cast::<u8>();
+        let offset = start.align_offset(layout.align());
+        if offset > self.storage.len() || self.storage.len() - offset < layout.size() {
+            return Err(AllocError);
+        }
+        self.taken.set(true);
+        let ptr = unsafe { start.add(offset) };
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, _: NonNull<u8>, _: Layout) {}
+}
+
+fn layout<T, A: Allocator>(cap: usize) -> Result<Layout, LayoutError> {
+    // `extend` places each field the same way `repr(C)` does for `Shared`,
+    // padding when `A` or `T` is more aligned than what comes before, and the
+    // padded size is what `Layout::for_value` gives back when freeing.
+    let layout = Layout::new::<Meta>()
+        .extend(Layout::new::<ManuallyDrop<A>>())?
+        .0
+        .extend(Layout::array::<T>(cap)?)?
+        .0;
+    Ok(layout.pad_to_align())
+}
+
+/// Writes the header into a fresh allocation and hands out both ends.
+///
+/// # Safety
+///
+/// `ptr` must come from `alloc` with the layout [`layout`] returns for `cap`.
+unsafe fn init<T, A: Allocator>(
+    ptr: NonNull<u8>,
+    cap: usize,
+    alloc: A,
+) -> (Sender<T, A>, Receiver<T, A>) {
+    let thing = unsafe { init_shared(ptr, cap, alloc) };
+    (Sender { ptr: thing }, Receiver::from_shared(thing))
+}
+
+/// Writes the header into a fresh allocation, marking both ends present.
+///
+/// # Safety
+///
+/// Same as [`init`].
+unsafe fn init_shared<T, A: Allocator>(
+    ptr: NonNull<u8>,
+    cap: usize,
+    alloc: A,
+) -> NonNull<Shared<T, A>> {
+    let thing = NonNull::<Shared<T, A>>::from_raw_parts(ptr, cap);
+    unsafe {
+        (&raw mut (*thing.as_ptr()).alloc).write(ManuallyDrop::new(alloc));
+        (&raw mut (*thing.as_ptr()).meta).write(Meta {
+            state: (TX | RX).into(),
+            cap,
+            head: CachePadded(0.into()),
+            tail: CachePadded(0.into()),
+            acked: 0.into(),
+            mask: cap.is_power_of_two().then(|| cap - 1),
+            wrap: if cap.is_power_of_two() {
+                0
+            } else {
+                usize::MAX - usize::MAX % cap
+            },
+            next: AtomicPtr::new(ptr::null_mut()),
+            tx_waker: AtomicWaker::new(),
+            rx_waker: AtomicWaker::new(),
+            #[cfg(feature = "metrics")]
+            total_sent: 0.into(),
+            #[cfg(feature = "metrics")]
+            send_full_rejections: 0.into(),
+            #[cfg(feature = "metrics")]
+            last_send_blocked: false.into(),
+            #[cfg(feature = "metrics")]
+            total_received: 0.into(),
+            #[cfg(feature = "heartbeat")]
+            epoch: Instant::now(),
+            #[cfg(feature = "heartbeat")]
+            last_active: 0.into(),
+        });
+    };
+    thing
+}
+
+/// Creates a queue that can hold up to `cap` elements, already holding the
+/// first `cap` elements of `items`.
+///
+/// Elements past the first `cap` are not taken from the iterator. Pass it by
+/// reference to keep them.
+///
+/// # Panics
+///
+/// Panics if `cap` is zero.
+pub fn from_iter_with_cap<T, I: IntoIterator<Item = T>>(
+    cap: usize,
+    items: I,
+) -> (Sender<T>, Receiver<T>) {
+    let (mut tx, rx) = new(cap);
+    tx.send_iter(items);
+    (tx, rx)
+}
+
+/// Creates a queue holding every element of `items`, just big enough for
+/// them.
+///
+/// An iterator whose size hint gives an exact length is sent straight into
+/// the queue; any other is collected first to find out how many elements
+/// there are. If the hint turns out to be short, what was sent is collected
+/// along with the rest, so nothing is lost. An empty iterator still gives a
+/// queue with room for one element.
+pub fn from_iter<T, I: IntoIterator<Item = T>>(items: I) -> (Sender<T>, Receiver<T>) {
+    let mut items = items.into_iter();
+    let mut all = match items.size_hint() {
+        (lower, Some(upper)) if lower == upper => {
+            let (mut tx, mut rx) = new(lower.max(1));
+            tx.send_iter(&mut items);
+            // Nothing makes a size hint true, so check it held up.
+            let Some(extra) = items.next() else {
+                return (tx, rx);
+            };
+            let mut all = rx.take_all();
+            all.push(extra);
+            all
+        }
+        _ => Vec::new(),
+    };
+    all.extend(items);
+    from_iter_with_cap(all.len().max(1), all)
+}
+
+/// Empties a queue and hands its two ends back, ready to be used like a new
+/// queue of the same capacity without allocating again.
+///
+/// # Panics
+///
+/// Panics if `tx` and `rx` don't belong to the same queue.
+pub fn recycle<T, A: Allocator>(
+    tx: Sender<T, A>,
+    mut rx: Receiver<T, A>,
+) -> (Sender<T, A>, Receiver<T, A>) {
+    rx.catch_up();
+    assert_eq!(
+        tx.ptr.cast::<()>(),
+        rx.ptr.cast::<()>(),
+        "sender and receiver belong to different queues"
+    );
+    rx.clear();
+    // Owning both ends means nothing else is looking at the queue. Both are
+    // present, and whatever else the last use of the queue flagged goes,
+    // along with a buffer the sender hasn't moved over to yet.
+    let meta = unsafe { &rx.ptr.as_ref().meta };
+    let state = meta.state.load(Ordering::Relaxed);
+    meta.state
+        .store(TX | RX | (state & FIXED), Ordering::Relaxed);
+    if let Some(next) = NonNull::new(meta.next.swap(ptr::null_mut(), Ordering::Relaxed)) {
+        unsafe { Shared::free(Shared::<T, A>::from_thin(next)) };
+    }
+    meta.head.store(0, Ordering::Relaxed);
+    meta.tail.store(0, Ordering::Relaxed);
+    meta.acked.store(0, Ordering::Relaxed);
+    (tx, rx)
+}
+
+/// An element received by [`try_select2`] or [`select2`], tagged with the
+/// receiver it came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+/// Receives from whichever of `a` and `b` has an element, trying `a` first.
+///
+/// Only reports `Disconnected` once both queues are empty and disconnected.
+pub fn try_select2<L, R, A1: Allocator, A2: Allocator>(
+    a: &mut Receiver<L, A1>,
+    b: &mut Receiver<R, A2>,
+) -> Result<Either<L, R>, TryRecvError> {
+    let a_err = match a.try_recv() {
+        Ok(el) => return Ok(Either::Left(el)),
+        Err(e) => e,
+    };
+    match b.try_recv() {
+        Ok(el) => Ok(Either::Right(el)),
+        Err(TryRecvError::Disconnected) if a_err == TryRecvError::Disconnected => {
+            Err(TryRecvError::Disconnected)
+        }
+        Err(_) => Err(TryRecvError::Empty),
+    }
+}
+
+/// Receives from whichever of `a` and `b` has an element first, blocking
+/// while both are empty.
+///
+/// Fails once both queues are empty and disconnected.
+#[cfg(feature = "std")]
+pub fn select2<L, R, A1: Allocator, A2: Allocator>(
+    a: &mut Receiver<L, A1>,
+    b: &mut Receiver<R, A2>,
+) -> Result<Either<L, R>, RecvError> {
+    loop {
+        let (a_ptr, b_ptr) = (a.ptr, b.ptr);
+        // Each receiver's slot only ever holds its own receiver's waker, and
+        // we have both receivers. One that stays registered after we return
+        // only costs a spurious unpark later. A receiver moving on to a new
+        // buffer frees its slot, so that starts over.
+        let slots = unsafe { [&a_ptr.as_ref().meta.rx_waker, &b_ptr.as_ref().meta.rx_waker] };
+        let res = waker::block_on(&slots, None, None, || match try_select2(a, b) {
+            Ok(el) => Some(Some(Ok(el))),
+            Err(TryRecvError::Disconnected) => Some(Some(Err(RecvError))),
+            Err(TryRecvError::Empty) if (a.ptr, b.ptr) != (a_ptr, b_ptr) => Some(None),
+            Err(TryRecvError::Empty) => None,
+        });
+        match res {
+            Some(None) => {}
+            res => return res.flatten().unwrap_or(Err(RecvError)),
+        }
+    }
+}
+
+/// Merges two receivers of the same element type into one blocking iterator.
+///
+/// Every element sent to either queue comes out exactly once, in order for
+/// each queue. The iterator ends once both senders are gone and both queues
+/// have been drained.
+#[cfg(feature = "std")]
+pub fn merge<T, A1: Allocator, A2: Allocator>(
+    a: Receiver<T, A1>,
+    b: Receiver<T, A2>,
+) -> MergeIter<T, A1, A2> {
+    MergeIter { a, b, flip: false }
+}
+
+/// Blocking iterator returned by [`merge`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct MergeIter<T, A1: Allocator = Global, A2: Allocator = Global> {
+    a: Receiver<T, A1>,
+    b: Receiver<T, A2>,
+    // Which receiver goes first next time, so a busy one can't starve the
+    // other.
+    flip: bool,
+}
+
+#[cfg(feature = "std")]
+impl<T, A1: Allocator, A2: Allocator> Iterator for MergeIter<T, A1, A2> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.flip = !self.flip;
+        let el = if self.flip {
+            select2(&mut self.a, &mut self.b).ok()?
+        } else {
+            select2(&mut self.b, &mut self.a).ok()?
+        };
+        Some(match el {
+            Either::Left(el) | Either::Right(el) => el,
+        })
+    }
+}
+
+/// The capacity [`new_default`] uses.
+///
+/// A power of two, so slots are found with a mask, and big enough to absorb
+/// bursts without taking up much memory for small elements.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// Creates a queue with room for [`DEFAULT_CAPACITY`] elements, for callers
+/// that don't need a particular size.
+pub fn new_default<T>() -> (Sender<T>, Receiver<T>) {
+    new_pow2(DEFAULT_CAPACITY)
+}
+
+/// Creates a queue whose capacity is `cap` rounded up to the next power of
+/// two, so slots are found with a mask rather than a division.
+///
+/// The queue really holds that many elements: `new_pow2(10)` gives 16 slots,
+/// and [`Sender::capacity`] and [`Receiver::capacity`] report 16.
+///
+/// # Panics
+///
+/// Panics if `cap` is zero or the rounded capacity overflows `usize`.
+pub fn new_pow2<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(cap > 0, "queue capacity must be greater than zero");
+    new(cap
+        .checked_next_power_of_two()
+        .expect("queue capacity overflows usize"))
+}
+
+/// Creates a queue with room for `cap` elements.
+///
+/// Unlike [`new_pow2`] this never rounds anything up, and unlike [`new`] the
+/// caller has already dealt with a capacity that isn't a power of two. The
+/// handles carry `cap`, so their own `try_send` and `try_recv` find slots by
+/// masking with it. Everything else goes through the plain handles they
+/// deref to.
+pub fn new_typed<T>(
+    cap: pow2::Pow2Cap,
+) -> (
+    pow2::Sender<T, pow2::Pow2Cap>,
+    pow2::Receiver<T, pow2::Pow2Cap>,
+) {
+    pow2::split(cap)
+}
+
+/// Creates a queue with room for `N` elements, checking at compile time that
+/// `N` is a power of two.
+///
+/// `N` is part of the handles' type, so their own `try_send` and `try_recv`
+/// find slots by masking with the constant `N - 1`. Everything else goes
+/// through the plain handles they deref to.
+pub fn new_const<const N: usize, T>() -> (
+    pow2::Sender<T, pow2::ConstCap<N>>,
+    pow2::Receiver<T, pow2::ConstCap<N>>,
+) {
+    pow2::split(pow2::ConstCap::new())
+}
+
+/// Creates a queue big enough to hold `max_latency` worth of elements
+/// arriving at `items_per_sec`, rounded up to a power of two like
+/// [`new_pow2`].
+///
+/// The queue has room for at least one element, even if the rate or the
+/// latency is zero.
+///
+/// # Panics
+///
+/// Panics if the rounded capacity overflows `usize`.
+#[cfg(feature = "std")]
+pub fn new_for_throughput<T>(
+    items_per_sec: usize,
+    max_latency: Duration,
+) -> (Sender<T>, Receiver<T>) {
+    let backlog = (items_per_sec as u128 * max_latency.as_nanos()).div_ceil(1_000_000_000);
+    new_pow2(usize::try_from(backlog).unwrap_or(usize::MAX).max(1))
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use std::prelude::rust_2024::*;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+    #[cfg(feature = "std")]
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::spsc::{
+        CLOSED, Either, RX, Receiver, SHUTDOWN_ACKED, Sender, TX, TryRecvError, TrySendError,
+        from_iter_with_cap, new, new_pow2,
+    };
+    #[cfg(feature = "std")]
+    use crate::spsc::{RecvError, RecvTimeoutError, SendError, SendTimeoutError, select2};
+
+    /// Moves both positions of an empty queue to `pos`.
+    fn seed<T>(tx: &mut Sender<T>, rx: &mut Receiver<T>, pos: usize) {
+        assert!(tx.is_empty() && rx.is_empty());
+        let meta = unsafe { &tx.ptr.as_ref().meta };
+        meta.head.store(pos, Ordering::Relaxed);
+        meta.tail.store(pos, Ordering::Relaxed);
+    }
+
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn create() {
+        let (mut tx, mut rx) = new::<u32>(10);
+        tx.try_send(10).unwrap();
+        tx.try_send(20).unwrap();
+        assert_eq!(rx.try_recv(), Ok(10));
+        assert_eq!(rx.try_recv(), Ok(20));
+    }
+
+    #[test]
+    fn drop_unreceived() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut tx, rx) = new(4);
+        for _ in 0..3 {
+            tx.try_send(DropCounter(drops.clone())).unwrap();
+        }
+        drop(tx);
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+        drop(rx);
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    #[should_panic = "capacity must be greater than zero"]
+    fn zero_capacity() {
+        let _ = new::<u32>(0);
+    }
+
+    #[test]
+    fn unit_capacity() {
+        let (mut tx, mut rx) = new::<u32>(1);
+        assert_eq!(tx.try_send(1), Ok(()));
+        assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(tx.try_send(3), Ok(()));
+        assert_eq!(rx.try_recv(), Ok(3));
+    }
+
+    #[test]
+    fn len() {
+        let (mut tx, mut rx) = new::<u32>(3);
+        assert_eq!(tx.capacity(), 3);
+        assert_eq!(rx.capacity(), 3);
+        assert!(tx.is_empty() && rx.is_empty());
+        tx.try_send(1).unwrap();
+        tx.try_send(2).unwrap();
+        assert_eq!((tx.len(), rx.len()), (2, 2));
+        assert!(!tx.is_empty() && !tx.is_full());
+        tx.try_send(3).unwrap();
+        assert!(tx.is_full() && rx.is_full());
+        rx.try_recv().unwrap();
+        assert_eq!((tx.len(), rx.len()), (2, 2));
+        assert!(!rx.is_full());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn blocking() {
+        const N: u32 = if cfg!(miri) { 1_000 } else { 100_000 };
+        let (mut tx, mut rx) = new::<u32>(4);
+        let producer = thread::spawn(move || {
+            for i in 0..N {
+                tx.send(i).unwrap();
+            }
+        });
+        for i in 0..N {
+            assert_eq!(rx.recv(), Ok(i));
+        }
+        producer.join().unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn close_wakes_receiver() {
+        let (tx, mut rx) = new::<u32>(4);
+        let consumer = thread::spawn(move || {
+            let start = Instant::now();
+            // Would only return after the full minute if nothing woke it.
+            let res = rx.recv_timeout(Duration::from_secs(60));
+            (res, start.elapsed(), rx.is_closed())
+        });
+        thread::sleep(Duration::from_millis(50));
+        tx.close();
+        let (res, waited, closed) = consumer.join().unwrap();
+        assert_eq!(res, Err(RecvTimeoutError::Disconnected));
+        assert!(waited < Duration::from_secs(10), "{waited:?}");
+        assert!(closed);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn drop_wakes_receiver() {
+        let (tx, mut rx) = new::<u32>(4);
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        let consumer = thread::spawn(move || done_tx.send(rx.recv()).unwrap());
+        let producer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(tx);
+        });
+        // A plain `recv` has no timeout of its own, so bound the wait here.
+        let res = done_rx.recv_timeout(Duration::from_secs(10));
+        assert_eq!(res, Ok(Err(RecvError)));
+        producer.join().unwrap();
+        consumer.join().unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn timeout() {
+        let (mut tx, mut rx) = new::<u32>(1);
+        let dur = Duration::from_millis(50);
+
+        let start = Instant::now();
+        assert_eq!(rx.recv_timeout(dur), Err(RecvTimeoutError::Timeout));
+        let waited = start.elapsed();
+        assert!(waited >= dur && waited < dur * 10, "{waited:?}");
+
+        tx.try_send(1).unwrap();
+        let start = Instant::now();
+        assert_eq!(tx.send_timeout(2, dur), Err(SendTimeoutError::Timeout(2)));
+        let waited = start.elapsed();
+        assert!(waited >= dur && waited < dur * 10, "{waited:?}");
+
+        assert_eq!(rx.recv_timeout(dur), Ok(1));
+    }
+
+    #[cfg(all(feature = "async", feature = "std"))]
+    #[test]
+    fn stream() {
+        use futures::{StreamExt, executor::block_on};
+
+        const N: u32 = 1000;
+        let (mut tx, mut rx) = new::<u32>(4);
+        let producer = thread::spawn(move || {
+            for i in 0..N {
+                tx.send(i).unwrap();
+            }
+        });
+        let got: Vec<u32> = block_on(rx.by_ref().take(N as usize).collect());
+        assert_eq!(got, (0..N).collect::<Vec<_>>());
+        producer.join().unwrap();
+        assert_eq!(block_on(rx.next()), None);
+    }
+
+    #[cfg(all(feature = "async", feature = "std"))]
+    #[test]
+    fn sink() {
+        use futures::{SinkExt, StreamExt, executor::block_on, stream};
+
+        const N: u32 = if cfg!(miri) { 50 } else { 1000 };
+        let (mut tx, mut rx) = new::<u32>(1);
+        let consumer = thread::spawn(move || {
+            let got: Vec<_> = block_on(rx.by_ref().collect());
+            (got, rx.is_closed())
+        });
+        // The inherent methods of the same names would shadow `SinkExt`'s.
+        block_on(async {
+            SinkExt::send(&mut tx, 0).await.unwrap();
+            SinkExt::send_all(&mut tx, &mut stream::iter(1..N).map(Ok))
+                .await
+                .unwrap();
+            SinkExt::close(&mut tx).await.unwrap();
+        });
+        drop(tx);
+        let (got, closed) = consumer.join().unwrap();
+        assert_eq!(got, (0..N).collect::<Vec<_>>());
+        assert!(closed);
+
+        let (mut tx, rx) = new::<u32>(1);
+        drop(rx);
+        assert_eq!(block_on(SinkExt::send(&mut tx, 1)), Err(SendError(())));
+
+        // Closed only once the sender is really gone.
+        let (mut tx, mut rx) = new::<u32>(2);
+        block_on(SinkExt::close(&mut tx)).unwrap();
+        assert!(!rx.is_closed());
+        assert_eq!(block_on(SinkExt::send(&mut tx, 1)), Err(SendError(())));
+        tx.try_send(2).unwrap();
+        drop(tx);
+        assert!(rx.is_closed());
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn send_async() {
+        use std::{
+            future::Future,
+            pin::pin,
+            sync::atomic::AtomicBool,
+            task::{Context, Poll, Wake, Waker},
+        };
+
+        struct Flag(AtomicBool);
+
+        impl Wake for Flag {
+            fn wake(self: Arc<Self>) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let (mut tx, mut rx) = new::<u32>(1);
+        tx.try_send(1).unwrap();
+        {
+            let mut fut = pin!(tx.send_async(2));
+            assert_eq!(fut.as_mut().poll(&mut cx), Poll::Pending);
+            assert!(!flag.0.load(Ordering::Relaxed));
+            assert_eq!(rx.try_recv(), Ok(1));
+            assert!(flag.0.load(Ordering::Relaxed));
+            assert_eq!(fut.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        }
+        assert_eq!(rx.try_recv(), Ok(2));
+
+        // A future dropped while pending never writes its element.
+        tx.try_send(3).unwrap();
+        {
+            let mut fut = pin!(tx.send_async(4));
+            assert_eq!(fut.as_mut().poll(&mut cx), Poll::Pending);
+        }
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn default_capacity() {
+        use crate::spsc::{DEFAULT_CAPACITY, new_default};
+
+        let (mut tx, mut rx) = new_default::<u8>();
+        assert_eq!(rx.capacity(), DEFAULT_CAPACITY);
+        assert_eq!(
+            unsafe { tx.ptr.as_ref() }.meta.mask,
+            Some(DEFAULT_CAPACITY - 1)
+        );
+        tx.try_send(7).unwrap();
+        assert_eq!(rx.try_recv(), Ok(7));
+    }
+
+    #[test]
+    fn pow2() {
+        let (tx, rx) = new_pow2::<u8>(10);
+        assert_eq!((tx.capacity(), rx.capacity()), (16, 16));
+        let (mut tx, mut rx) = new_pow2::<usize>(5);
+        assert_eq!(tx.capacity(), 8);
+        assert_eq!(unsafe { tx.ptr.as_ref() }.meta.mask, Some(7));
+        let (odd, _odd_rx) = new::<u8>(5);
+        assert_eq!(unsafe { odd.ptr.as_ref() }.meta.mask, None);
+
+        let start = usize::MAX / 2 - 20;
+        seed(&mut tx, &mut rx, start);
+        for round in 0..1000 {
+            let len = round % 8 + 1;
+            for i in 0..len {
+                tx.try_send(round * 8 + i).unwrap();
+            }
+            for i in 0..len {
+                assert_eq!(rx.try_recv(), Ok(round * 8 + i));
+            }
+        }
+        let head = unsafe { tx.ptr.as_ref() }.meta.head.load(Ordering::Relaxed);
+        assert!(head > usize::MAX / 2);
+    }
+
+    #[test]
+    fn padding() {
+        use std::mem::offset_of;
+
+        use crate::spsc::Meta;
+
+        let line = |offset: usize| offset / 64;
+        let head = line(offset_of!(Meta, head));
+        let tail = line(offset_of!(Meta, tail));
+        assert_ne!(head, tail);
+        let state = line(offset_of!(Meta, state));
+        assert_ne!(state, head);
+        assert_ne!(state, tail);
+        assert_eq!(std::mem::align_of::<Meta>() % 64, 0);
+    }
+
+    #[test]
+    fn send_slice() {
+        let (mut tx, mut rx) = new::<u8>(5);
+        assert_eq!(tx.try_send_slice(&[1, 2, 3]), 3);
+        // Only two slots are left.
+        assert_eq!(tx.try_send_slice(&[4, 5, 6]), 2);
+        assert_eq!(tx.try_send_slice(&[7]), 0);
+        for i in 1..=5 {
+            assert_eq!(rx.try_recv(), Ok(i));
+        }
+
+        // `head` sits at index 0 after wrapping; move it to index 3 and then
+        // fill across the end of the buffer.
+        assert_eq!(tx.try_send_slice(&[0, 0, 0]), 3);
+        for _ in 0..3 {
+            rx.try_recv().unwrap();
+        }
+        assert_eq!(tx.try_send_slice(&[1, 2, 3, 4, 5, 6]), 5);
+        for i in 1..=5 {
+            assert_eq!(rx.try_recv(), Ok(i));
+        }
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn recv_slice() {
+        let (mut tx, mut rx) = new::<u8>(5);
+        let mut buf = [0; 8];
+        assert_eq!(rx.try_recv_slice(&mut buf), 0);
+
+        // Asking for more than is queued only returns what is there.
+        tx.try_send_slice(&[1, 2, 3]);
+        assert_eq!(rx.try_recv_slice(&mut buf), 3);
+        assert_eq!(buf[..3], [1, 2, 3]);
+
+        // `tail` sits at index 3, so this read crosses the end of the buffer.
+        tx.try_send_slice(&[4, 5, 6, 7]);
+        assert_eq!(rx.try_recv_slice(&mut buf[..3]), 3);
+        assert_eq!(buf[..3], [4, 5, 6]);
+        assert_eq!(rx.try_recv_slice(&mut buf), 1);
+        assert_eq!(buf[0], 7);
+        assert_eq!(rx.try_recv_slice(&mut buf), 0);
+    }
+
+    #[test]
+    fn try_iter() {
+        let (mut tx, mut rx) = new::<u32>(8);
+        for i in 0..5 {
+            tx.try_send(i).unwrap();
+        }
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+        assert!(rx.is_empty());
+        assert_eq!(rx.try_iter().next(), None);
+    }
+
+    #[test]
+    fn send_len() {
+        let (mut tx, mut rx) = new(4);
+        for i in 1..=4 {
+            assert_eq!(tx.try_send_len(i), Ok(i));
+        }
+        assert_eq!(tx.try_send_len(5), Err(TrySendError::Full(5)));
+        rx.try_recv().unwrap();
+        assert_eq!(tx.try_send_len(5), Ok(4));
+    }
+
+    #[test]
+    fn as_ptr() {
+        let (mut tx, mut rx) = new::<u32>(4);
+        tx.send_iter(0..3);
+        rx.skip(2);
+        tx.send_iter(3..6);
+        // The live region runs past the last slot.
+        let (head, tail) = rx.debug_positions();
+        let base = rx.as_ptr();
+        let live: Vec<_> = (tail..head)
+            .map(|pos| unsafe { (*base.add(pos % rx.capacity())).assume_init() })
+            .collect();
+        assert_eq!(live, [2, 3, 4, 5]);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), live);
+    }
+
+    #[test]
+    fn iter_with_errors() {
+        let (mut tx, mut rx) = new::<u32>(4);
+        tx.send_iter(0..2);
+        let mut iter = rx.iter_with_errors();
+        assert_eq!(iter.next(), Some(Ok(0)));
+        assert_eq!(iter.next(), Some(Ok(1)));
+        assert_eq!(iter.next(), Some(Err(TryRecvError::Empty)));
+        tx.try_send(2).unwrap();
+        drop(tx);
+        let mut iter = rx.iter_with_errors();
+        assert_eq!(iter.next(), Some(Ok(2)));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn chunks() {
+        let (mut tx, mut rx) = new::<u32>(8);
+        tx.send_iter(0..7);
+        let chunks: Vec<_> = rx.chunks(3).collect();
+        assert_eq!(chunks, [vec![0, 1, 2], vec![3, 4, 5], vec![6]]);
+        assert!(rx.is_empty());
+        assert_eq!(rx.chunks(3).next(), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn into_iter() {
+        const N: u32 = if cfg!(miri) { 500 } else { 10_000 };
+        let (mut tx, rx) = new::<u32>(4);
+        let producer = thread::spawn(move || {
+            for i in 0..N {
+                tx.send(i).unwrap();
+            }
+        });
+        let mut expected = 0;
+        for el in rx {
+            assert_eq!(el, expected);
+            expected += 1;
+        }
+        assert_eq!(expected, N);
+        producer.join().unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn into_iter_fused() {
+        let (mut tx, rx) = new::<u32>(4);
+        tx.try_send(1).unwrap();
+        drop(tx);
+        let mut iter = rx.into_iter();
+        assert_eq!(iter.next(), Some(1));
+        for _ in 0..3 {
+            assert_eq!(iter.next(), None);
+        }
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn peek() {
+        let (mut tx, mut rx) = new::<String>(2);
+        assert_eq!(rx.peek(), None);
+        tx.try_send("a".to_owned()).unwrap();
+        tx.try_send("b".to_owned()).unwrap();
+        assert_eq!(rx.peek().map(String::as_str), Some("a"));
+        assert_eq!(rx.peek().map(String::as_str), Some("a"));
+        assert_eq!(rx.try_recv().as_deref(), Ok("a"));
+        assert_eq!(rx.peek().map(String::as_str), Some("b"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn drop_race() {
+        const N: usize = if cfg!(miri) { 100 } else { 10_000 };
+        let drops = Arc::new(AtomicUsize::new(0));
+        for _ in 0..N {
+            let (mut tx, rx) = new(1);
+            tx.try_send(DropCounter(drops.clone())).unwrap();
+            let handle = thread::spawn(move || drop(tx));
+            drop(rx);
+            handle.join().unwrap();
+        }
+        // Every allocation was freed, and freed once.
+        assert_eq!(drops.load(Ordering::Relaxed), N);
+    }
+
+    #[test]
+    fn disconnect() {
+        let (mut tx, rx) = new(4);
+        drop(rx);
+        assert_eq!(tx.try_send(1), Err(TrySendError::Disconnected(1)));
+        // Nothing was written for a receiver that will never read it.
+        assert!(tx.is_empty());
+
+        // A dead receiver beats a full queue.
+        let (mut tx, rx) = new(1);
+        tx.try_send(1).unwrap();
+        drop(rx);
+        assert_eq!(tx.try_send(2), Err(TrySendError::Disconnected(2)));
+
+        let (mut tx, mut rx) = new(4);
+        tx.try_send(1).unwrap();
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        tx.try_send(2).unwrap();
+        drop(tx);
+        // Whatever was sent before the drop is still delivered.
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn send_iter() {
+        let (mut tx, mut rx) = new(10);
+        let mut iter = 0..100;
+        assert_eq!(tx.send_iter(&mut iter), 10);
+        // Nothing was pulled that didn't fit.
+        assert_eq!(iter.next(), Some(10));
+        assert_eq!(tx.send_iter(&mut iter), 0);
+
+        for i in 0..4 {
+            assert_eq!(rx.try_recv(), Ok(i));
+        }
+        // Wraps around the end of the buffer.
+        assert_eq!(tx.send_iter([20, 21]), 2);
+        let rest: Vec<_> = rx.try_iter().collect();
+        assert_eq!(rest, [4, 5, 6, 7, 8, 9, 20, 21]);
+    }
+
+    #[test]
+    fn close() {
+        let (mut tx, mut rx) = new(4);
+        tx.try_send(1).unwrap();
+        tx.try_send(2).unwrap();
+        tx.close();
+        // Queued elements are drained before the end of the stream shows.
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert!(rx.is_closed());
+
+        let (tx, mut rx) = new::<i32>(4);
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert!(!rx.is_closed());
+
+        let (mut tx, rx) = new(4);
+        rx.close();
+        assert_eq!(tx.try_send(1), Err(TrySendError::Disconnected(1)));
+    }
+
+    #[test]
+    fn is_connected() {
+        let (tx, rx) = new::<i32>(4);
+        assert!(tx.is_connected());
+        drop(rx);
+        assert!(!tx.is_connected());
+
+        let (tx, rx) = new::<i32>(4);
+        assert!(rx.is_connected());
+        drop(tx);
+        assert!(!rx.is_connected());
+    }
+
+    #[test]
+    fn spare_capacity() {
+        let (mut tx, mut rx) = new(8);
+        assert_eq!(tx.spare_capacity(), 8);
+        assert_eq!(tx.send_iter(0..4), 4);
+        assert_eq!(tx.spare_capacity(), 4);
+        rx.try_recv().unwrap();
+        assert_eq!(tx.spare_capacity(), 5);
+        assert_eq!(tx.send_iter(0..5), 5);
+        assert_eq!(tx.spare_capacity(), 0);
+    }
+
+    #[test]
+    fn reserve() {
+        let (mut tx, mut rx) = new::<[u64; 32]>(2);
+        let mut slot = tx.reserve().unwrap();
+        let ptr = slot.as_mut_ptr().cast::<u64>();
+        for i in 0..32 {
+            unsafe { ptr.add(i).write(i as u64) };
+        }
+        unsafe { slot.commit() };
+        tx.reserve().unwrap().write([7; 32]);
+        assert!(tx.reserve().is_none());
+
+        let el = rx.try_recv().unwrap();
+        assert!(el.iter().enumerate().all(|(i, &x)| x == i as u64));
+        assert_eq!(rx.try_recv(), Ok([7; 32]));
+    }
+
+    #[test]
+    fn reserve_slice() {
+        let (mut tx, mut rx) = new::<u32>(8);
+        tx.send_iter(0..5);
+        rx.skip(5);
+        // Only three slots are left before the end of the buffer.
+        let mut run = tx.reserve_slice(usize::MAX);
+        assert_eq!(run.len(), 3);
+        run[0].write(10);
+        run[1].write(11);
+        unsafe { run.commit(2) };
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [10, 11]);
+
+        // The rest of the way round.
+        let mut run = tx.reserve_slice(4);
+        assert_eq!(run.len(), 1);
+        run[0].write(12);
+        unsafe { run.commit(1) };
+        let mut run = tx.reserve_slice(4);
+        assert_eq!(run.len(), 4);
+        for (i, slot) in run.iter_mut().enumerate() {
+            slot.write(13 + i as u32);
+        }
+        unsafe { run.commit(4) };
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [12, 13, 14, 15, 16]);
+
+        tx.send_iter(0..8);
+        assert!(tx.reserve_slice(4).is_empty());
+    }
+
+    #[test]
+    #[should_panic = "committing more slots than were reserved"]
+    fn commit_past_reservation() {
+        let (mut tx, _rx) = new::<u32>(4);
+        let run = tx.reserve_slice(2);
+        unsafe { run.commit(3) };
+    }
+
+    #[test]
+    fn drain_filter() {
+        let (mut tx, mut rx) = new(8);
+        tx.send_iter([1, 2, 3, 10, 4]);
+        assert_eq!(rx.drain_filter(|x| *x < 5), [1, 2, 3]);
+        // Stuck behind the first rejected element.
+        assert!(rx.drain_filter(|x| *x < 5).is_empty());
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [10, 4]);
+        assert!(rx.drain_filter(|_| true).is_empty());
+    }
+
+    #[test]
+    fn reserve_wrapped() {
+        let (mut tx, mut rx) = new::<u32>(8);
+        tx.send_iter(0..6);
+        rx.skip(4);
+        let mut runs = tx.reserve_wrapped(5);
+        let (first, second) = runs.as_mut_slices();
+        assert_eq!((first.len(), second.len()), (2, 3));
+        for (slot, el) in first.iter_mut().chain(second).zip(10..) {
+            slot.write(el);
+        }
+        // Nothing shows up before both runs are committed.
+        assert_eq!(rx.len(), 2);
+        unsafe { runs.commit_wrapped(2, 3) };
+        assert_eq!(rx.len(), 7);
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            [4, 5, 10, 11, 12, 13, 14]
+        );
+
+        // Not up to the end of the buffer, so there is no second run.
+        let mut runs = tx.reserve_wrapped(4);
+        assert_eq!(runs.as_mut_slices().0.len(), 4);
+        assert!(runs.as_mut_slices().1.is_empty());
+    }
+
+    #[test]
+    #[should_panic = "committed runs leave a gap"]
+    fn commit_wrapped_gap() {
+        let (mut tx, mut rx) = new::<u32>(4);
+        tx.send_iter(0..2);
+        rx.skip(2);
+        let runs = tx.reserve_wrapped(4);
+        unsafe { runs.commit_wrapped(1, 1) };
+    }
+
+    #[test]
+    fn reserve_uncommitted() {
+        let (mut tx, mut rx) = new(2);
+        {
+            let mut slot = tx.reserve().unwrap();
+            // Written but never committed.
+            (*slot).write(1);
+        }
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(tx.len(), 0);
+    }
+
+    #[test]
+    fn recv_ref() {
+        let (mut tx, mut rx) = new(2);
+        assert!(rx.recv_ref().is_none());
+        tx.try_send(1).unwrap();
+        tx.try_send(2).unwrap();
+
+        // Looking without taking leaves the element in place.
+        assert_eq!(*rx.recv_ref().unwrap(), 1);
+        let guard = rx.recv_ref().unwrap();
+        assert_eq!(*guard, 1);
+        assert_eq!(guard.take(), 1);
+        assert_eq!(tx.spare_capacity(), 1);
+
+        rx.recv_ref().unwrap().commit();
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn clear() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut tx, mut rx) = new(4);
+        rx.clear();
+        for _ in 0..3 {
+            tx.try_send(DropCounter(drops.clone())).unwrap();
+        }
+        rx.clear();
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+        assert!(rx.is_empty());
+        assert_eq!(tx.spare_capacity(), 4);
+        // The queue keeps working from where `clear` left it.
+        tx.try_send(DropCounter(drops.clone())).unwrap();
+        assert!(rx.try_recv().is_ok());
+    }
+
+    #[test]
+    fn prefill() {
+        let (mut tx, mut rx) = from_iter_with_cap(5, [1, 2, 3]);
+        assert_eq!(tx.spare_capacity(), 2);
+        tx.try_send(4).unwrap();
+        let all: Vec<_> = rx.try_iter().collect();
+        assert_eq!(all, [1, 2, 3, 4]);
+
+        // Whatever doesn't fit stays in the iterator.
+        let mut items = 0..10;
+        let (_tx, rx) = from_iter_with_cap(4, &mut items);
+        assert_eq!(rx.len(), 4);
+        assert_eq!(items.next(), Some(4));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn spin() {
+        let (mut tx, mut rx) = new(2);
+        tx.send_spin(1, 100).unwrap();
+        assert_eq!(rx.recv_spin(100), Ok(1));
+
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            tx.send_spin(2, 100).unwrap();
+            tx.send_spin(3, 0).unwrap();
+        });
+        // Runs out of spins and parks until the sender shows up.
+        assert_eq!(rx.recv_spin(10), Ok(2));
+        assert_eq!(rx.recv_spin(10), Ok(3));
+        handle.join().unwrap();
+        assert_eq!(rx.recv_spin(10), Err(RecvError));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn slow_producer() {
+        const N: u32 = 200;
+        let (mut tx, mut rx) = new(4);
+        let handle = thread::spawn(move || {
+            for i in 0..N {
+                if i % 20 == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                tx.send(i).unwrap();
+            }
+        });
+        for i in 0..N {
+            assert_eq!(rx.recv(), Ok(i));
+        }
+        assert_eq!(rx.recv(), Err(RecvError));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn recv_many() {
+        let (mut tx, mut rx) = new(5);
+        tx.send_iter((0..5).map(|i| i.to_string()));
+        assert_eq!(rx.recv_many(3), ["0", "1", "2"]);
+        assert_eq!(rx.len(), 2);
+        assert_eq!(rx.recv_many(10), ["3", "4"]);
+        assert!(rx.recv_many(10).is_empty());
+    }
+
+    #[test]
+    fn wrap() {
+        for cap in [1, 6, 7, 8, 10] {
+            let (mut tx, mut rx) = new::<usize>(cap);
+            // Start a few laps short of where the positions wrap.
+            let end = match unsafe { tx.ptr.as_ref() }.meta.wrap {
+                0 => 0usize,
+                wrap => wrap,
+            };
+            seed(&mut tx, &mut rx, end.wrapping_sub(2 * cap + 1));
+            let mut next = 0;
+            for round in 0..4 * cap {
+                let len = round % cap + 1;
+                for i in 0..len {
+                    tx.try_send(next + i).unwrap();
+                }
+                assert_eq!(rx.len(), len);
+                if len == cap {
+                    assert!(tx.is_full());
+                    assert_eq!(tx.try_send(0), Err(TrySendError::Full(0)));
+                }
+                for _ in 0..len {
+                    assert_eq!(rx.try_recv(), Ok(next));
+                    next += 1;
+                }
+                assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+            }
+
+            // Bulk copies across the wrap as well.
+            seed(&mut tx, &mut rx, end.wrapping_sub(1));
+            let src: Vec<_> = (0..cap).collect();
+            assert_eq!(tx.try_send_slice(&src), cap);
+            let mut dst = vec![0; cap];
+            assert_eq!(rx.try_recv_slice(&mut dst), cap);
+            assert_eq!(dst, src);
+        }
+    }
+
+    #[test]
+    fn zero_sized() {
+        let (mut tx, mut rx) = new::<()>(8);
+        for round in 0..100 {
+            let len = round % 8 + 1;
+            for i in 0..len {
+                assert_eq!(rx.len(), i);
+                tx.try_send(()).unwrap();
+            }
+            if len == 8 {
+                assert_eq!(tx.try_send(()), Err(TrySendError::Full(())));
+            }
+            for i in (0..len).rev() {
+                assert_eq!(rx.try_recv(), Ok(()));
+                assert_eq!(rx.len(), i);
+            }
+            assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        }
+
+        // Only the counts live in the queue, but destructors still run.
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Zst;
+        impl Drop for Zst {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        // Miri walks every cell when it retags the buffer, so keep it small
+        // there.
+        let (mut tx, rx) = new(if cfg!(miri) { 64 } else { usize::MAX });
+        for _ in 0..5 {
+            assert!(tx.try_send(Zst).is_ok());
+        }
+        assert_eq!(rx.len(), 5);
+        drop((tx, rx));
+        assert_eq!(DROPS.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn over_aligned() {
+        use crate::spsc::Meta;
+
+        #[repr(align(128))]
+        #[derive(Debug, PartialEq)]
+        struct A(u8);
+
+        // `Meta` is aligned to 64 bytes, less than `A`.
+        assert!(std::mem::align_of::<Meta>() < std::mem::align_of::<A>());
+        let (mut tx, mut rx) = new(3);
+        let shared = unsafe { tx.ptr.as_ref() };
+        for slot in &shared.buffer {
+            assert_eq!(slot.get().align_offset(128), 0);
+        }
+        for i in 0..3 {
+            tx.try_send(A(i)).unwrap();
+        }
+        let guard = rx.recv_ref().unwrap();
+        assert_eq!((&*guard as *const A).align_offset(128), 0);
+        assert_eq!(guard.take(), A(0));
+        assert_eq!(rx.try_recv(), Ok(A(1)));
+    }
+
+    #[test]
+    fn new_in() {
+        use std::{
+            alloc::{AllocError, Allocator, Global, Layout},
+            ptr::NonNull,
+        };
+
+        use crate::spsc::new_in;
+
+        #[derive(Default)]
+        struct Tracking {
+            allocs: AtomicUsize,
+            frees: AtomicUsize,
+        }
+
+        unsafe impl Allocator for &Tracking {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                self.allocs.fetch_add(1, Ordering::Relaxed);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                self.frees.fetch_add(1, Ordering::Relaxed);
+                unsafe { Global.deallocate(ptr, layout) }
+            }
+        }
+
+        let tracking = Tracking::default();
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut tx, mut rx) = new_in(4, &tracking);
+        assert_eq!(tracking.allocs.load(Ordering::Relaxed), 1);
+        for _ in 0..3 {
+            tx.try_send(DropCounter(drops.clone())).unwrap();
+        }
+        drop(rx.try_recv().unwrap());
+        drop(tx);
+        assert_eq!(tracking.frees.load(Ordering::Relaxed), 0);
+        drop(rx);
+        assert_eq!(tracking.allocs.load(Ordering::Relaxed), 1);
+        assert_eq!(tracking.frees.load(Ordering::Relaxed), 1);
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn raw() {
+        let (tx, rx) = new::<String>(3);
+        let (tx, rx) = (tx.into_raw(), rx.into_raw());
+        let mut tx = unsafe { Sender::<String>::from_raw(tx) };
+        let mut rx = unsafe { Receiver::<String>::from_raw(rx) };
+        assert_eq!(tx.capacity(), 3);
+        tx.try_send("hello".to_string()).unwrap();
+        assert_eq!(rx.try_recv().as_deref(), Ok("hello"));
+    }
+
+    #[cfg(all(feature = "metrics", feature = "std"))]
+    #[test]
+    fn raw_with_log() {
+        let (mut tx, mut rx) = new::<String>(3);
+        tx.send_iter(["a", "b", "c"].map(String::from));
+        for _ in 0..3 {
+            rx.try_recv().unwrap();
+            // Each trip through a raw pointer drops the log it had.
+            rx = unsafe { Receiver::<String>::from_raw(rx.into_raw()) };
+        }
+        assert_eq!(rx.throughput(Duration::from_secs(1)), 0.0);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn panic_while_sending() {
+        use std::panic::{AssertUnwindSafe, catch_unwind};
+
+        fn build() -> String {
+            std::panic!("failed to build the element")
+        }
+
+        let (mut tx, mut rx) = new(4);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            tx.reserve().unwrap().write(build());
+        }));
+        assert!(result.is_err());
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let iter = (0..3).map(|i| if i < 2 { i.to_string() } else { build() });
+            tx.send_iter(iter);
+        }));
+        assert!(result.is_err());
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(tx.spare_capacity(), 4);
+
+        // The slots that were written to are reused as usual.
+        tx.send_iter(["a".to_string(), "b".to_string()]);
+        assert_eq!(rx.recv_many(4), ["a", "b"]);
+    }
+
+    #[test]
+    fn abandoned_reservations() {
+        use std::panic::{AssertUnwindSafe, catch_unwind};
+
+        fn build() -> DropCounter {
+            std::panic!("failed to build the element")
+        }
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut tx, rx) = new(4);
+        tx.try_send(DropCounter(drops.clone())).unwrap();
+        // Reserved and never written.
+        assert!(tx.reserve().is_some());
+        // Reserved and the value failed to build.
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            tx.reserve().unwrap().write(build());
+        }));
+        assert!(result.is_err());
+        // Neither reservation moved `head`.
+        assert_eq!(tx.len(), 1);
+        drop((tx, rx));
+        // Only the one element that was sent gets dropped, and Miri checks
+        // that no uninitialized slot was touched.
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn panic_while_receiving() {
+        use std::panic::{AssertUnwindSafe, catch_unwind};
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut tx, mut rx) = new(8);
+        for _ in 0..5 {
+            tx.try_send(DropCounter(drops.clone())).unwrap();
+        }
+        let mut seen = 0;
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            for el in rx.try_iter() {
+                seen += 1;
+                if seen == 2 {
+                    std::panic!("failed to process {:p}", &el);
+                }
+            }
+        }));
+        assert!(result.is_err());
+        // The element being processed was the caller's and was dropped while
+        // unwinding; the rest are still queued.
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+        assert_eq!(rx.len(), 3);
+
+        // A panic while looking at a borrowed element leaves it in place.
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let guard = rx.recv_ref().unwrap();
+            std::panic!("failed to process {:p}", &*guard);
+        }));
+        assert!(result.is_err());
+        assert_eq!(rx.len(), 3);
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+
+        tx.try_send(DropCounter(drops.clone())).unwrap();
+        assert_eq!(rx.drain().count(), 4);
+        assert_eq!(drops.load(Ordering::Relaxed), 6);
+        assert_eq!(tx.spare_capacity(), 8);
+    }
+
+    #[test]
+    fn recycle() {
+        use crate::spsc::recycle;
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut tx, mut rx) = new(4);
+        for _ in 0..6 {
+            tx.try_send(DropCounter(drops.clone())).unwrap();
+            rx.try_recv().unwrap();
+        }
+        for _ in 0..3 {
+            tx.try_send(DropCounter(drops.clone())).unwrap();
+        }
+        let before = unsafe { tx.ptr.as_ref() } as *const _;
+        // As if the stream had been marked closed without the sender going.
+        unsafe { tx.ptr.as_ref() }
+            .meta
+            .state
+            .fetch_or(CLOSED, Ordering::Relaxed);
+        assert!(rx.is_closed());
+        // And a shutdown handshake that already went through.
+        tx.begin_shutdown();
+        rx.ack_shutdown();
+
+        let (mut tx, mut rx) = recycle(tx, rx);
+        assert!(!rx.is_closed() && !rx.is_draining());
+        assert_eq!(
+            unsafe { tx.ptr.as_ref() }
+                .meta
+                .state
+                .load(Ordering::Relaxed),
+            TX | RX
+        );
+        assert_eq!(drops.load(Ordering::Relaxed), 9);
+        assert_eq!(unsafe { tx.ptr.as_ref() } as *const _, before);
+        assert_eq!(
+            unsafe { tx.ptr.as_ref() }.meta.head.load(Ordering::Relaxed),
+            0
+        );
+        assert!(rx.is_empty());
+        assert_eq!(tx.spare_capacity(), 4);
+        tx.try_send(DropCounter(drops.clone())).unwrap();
+        assert!(rx.try_recv().is_ok());
+    }
+
+    #[test]
+    #[should_panic = "different queues"]
+    fn channel_mismatched() {
+        use crate::spsc::Channel;
+
+        let (tx, _) = new::<u8>(1);
+        let (_, rx) = new::<u8>(4);
+        let _ = Channel::from((tx, rx));
+    }
+
+    #[test]
+    #[should_panic = "different queues"]
+    fn recycle_mismatched() {
+        use crate::spsc::recycle;
+
+        let (tx, _) = new::<u8>(1);
+        let (_, rx) = new::<u8>(1);
+        recycle(tx, rx);
+    }
+
+    #[test]
+    fn debug() {
+        struct Opaque;
+
+        let (mut tx, rx) = new(16);
+        for _ in 0..3 {
+            assert!(tx.try_send(Opaque).is_ok());
+        }
+        assert_eq!(
+            format!("{tx:?}"),
+            "Sender { capacity: 16, len: 3, connected: true }"
+        );
+        drop(tx);
+        assert_eq!(
+            format!("{rx:?}"),
+            "Receiver { capacity: 16, len: 3, connected: false }"
+        );
+    }
+
+    #[test]
+    fn as_slices() {
+        let (mut tx, mut rx) = new(5);
+        assert_eq!(rx.as_slices(), (&[][..], &[][..]));
+        tx.send_iter(0..4);
+        assert_eq!(rx.as_slices(), (&[0, 1, 2, 3][..], &[][..]));
+        assert_eq!(rx.recv_many(3), [0, 1, 2]);
+        // Fills across the end of the buffer.
+        tx.send_iter(4..8);
+        assert_eq!(rx.as_slices(), (&[3, 4][..], &[5, 6, 7][..]));
+    }
+
+    #[test]
+    fn try_select2() {
+        use crate::spsc::try_select2;
+
+        let (mut tx_a, mut rx_a) = new::<u8>(2);
+        let (tx_b, mut rx_b) = new::<&str>(2);
+        assert_eq!(try_select2(&mut rx_a, &mut rx_b), Err(TryRecvError::Empty));
+        tx_a.try_send(1).unwrap();
+        drop(tx_a);
+        assert_eq!(try_select2(&mut rx_a, &mut rx_b), Ok(Either::Left(1)));
+        // `b` is still connected.
+        assert_eq!(try_select2(&mut rx_a, &mut rx_b), Err(TryRecvError::Empty));
+        drop(tx_b);
+        assert_eq!(
+            try_select2(&mut rx_a, &mut rx_b),
+            Err(TryRecvError::Disconnected)
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn select2_blocking() {
+        let (_tx_a, mut rx_a) = new::<u8>(2);
+        let (mut tx_b, mut rx_b) = new(2);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            tx_b.send(7).unwrap();
+        });
+        // Only `b` ever sends.
+        assert_eq!(select2(&mut rx_a, &mut rx_b), Ok(Either::Right(7)));
+        handle.join().unwrap();
+
+        let (tx_a, mut rx_a) = new::<u8>(2);
+        drop(tx_a);
+        assert_eq!(select2(&mut rx_a, &mut rx_b), Err(RecvError));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn merge() {
+        const N: u32 = if cfg!(miri) { 100 } else { 10_000 };
+        let (mut tx_a, rx_a) = new(4);
+        let (mut tx_b, rx_b) = new(4);
+        let a = thread::spawn(move || {
+            for i in 0..N {
+                tx_a.send(i).unwrap();
+            }
+        });
+        let b = thread::spawn(move || {
+            for i in N..2 * N {
+                tx_b.send(i).unwrap();
+            }
+        });
+        let mut got: Vec<_> = crate::spsc::merge(rx_a, rx_b).collect();
+        a.join().unwrap();
+        b.join().unwrap();
+        // Each queue's elements come out in order.
+        assert!(got.iter().filter(|&&i| i < N).is_sorted());
+        assert!(got.iter().filter(|&&i| i >= N).is_sorted());
+        got.sort_unstable();
+        assert!(got.into_iter().eq(0..2 * N));
+    }
+
+    #[test]
+    fn debug_positions() {
+        let (mut tx, mut rx) = new(8);
+        for i in 0..5 {
+            tx.try_send(i).unwrap();
+        }
+        rx.try_recv().unwrap();
+        let (head, tail) = tx.debug_positions();
+        assert_eq!((head, tail), (5, 1));
+        assert_eq!(rx.debug_positions(), (head, tail));
+        assert_eq!(head - tail, rx.len());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn stats() {
+        use crate::spsc::Stats;
+
+        let (mut tx, mut rx) = new(10);
+        for i in 0..10 {
+            tx.try_send(i).unwrap();
+        }
+        for _ in 0..2 {
+            assert!(tx.try_send(10).is_err());
+        }
+        for _ in 0..7 {
+            rx.try_recv().unwrap();
+        }
+        let stats = Stats {
+            total_sent: 10,
+            total_received: 7,
+            send_full_rejections: 2,
+        };
+        assert_eq!(tx.stats(), stats);
+        assert_eq!(rx.stats(), stats);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn last_send_blocked() {
+        let (mut tx, mut rx) = new(2);
+        assert!(!tx.last_send_blocked());
+        tx.try_send(0).unwrap();
+        tx.try_send(1).unwrap();
+        assert!(!tx.last_send_blocked());
+        assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+        assert!(tx.last_send_blocked());
+        assert_eq!(rx.try_recv(), Ok(0));
+        // Still set until a send goes through.
+        assert!(tx.last_send_blocked());
+        tx.try_send(2).unwrap();
+        assert!(!tx.last_send_blocked());
+    }
+
+    #[test]
+    fn drain_handler() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let drained = Rc::new(RefCell::new(Vec::new()));
+        let sink = drained.clone();
+        let (mut tx, rx) = new(8);
+        for i in 0..5 {
+            tx.try_send(i).unwrap();
+        }
+        let mut rx = rx.with_drain_handler(move |el| sink.borrow_mut().push(el));
+        assert_eq!(rx.try_recv(), Ok(0));
+        drop(rx);
+        assert_eq!(*drained.borrow(), [1, 2, 3, 4]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn drain_handler_busy_sender() {
+        let (mut tx, rx) = new(4);
+        tx.send_iter(0..4);
+        let mut drained = Vec::new();
+        // Slow enough for the producer to refill every slot the drain frees.
+        let rx = rx.with_drain_handler(|el| {
+            drained.push(el);
+            thread::sleep(Duration::from_millis(1));
+        });
+        thread::scope(|s| {
+            let producer = s.spawn(move || {
+                let mut sent = 4;
+                loop {
+                    match tx.try_send(sent) {
+                        Ok(()) => sent += 1,
+                        Err(TrySendError::Full(_)) => thread::yield_now(),
+                        Err(TrySendError::Disconnected(_)) => return sent,
+                    }
+                }
+            });
+            drop(rx);
+            // It did get to send while the drain ran.
+            assert!(producer.join().unwrap() > 4);
+        });
+        // Only what was queued when the drain started.
+        assert_eq!(drained, [0, 1, 2, 3]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn io_pipe() {
+        use std::io::{ErrorKind, Read, Write};
+
+        let (mut tx, mut rx) = new(8);
+        let mut buf = [0; 8];
+        assert_eq!(rx.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+        tx.write_all(b"hello").unwrap();
+        // Only three more bytes fit.
+        assert_eq!(tx.write(b" world").unwrap(), 3);
+        assert_eq!(tx.write(b"rld").unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(rx.read(&mut buf[..4]).unwrap(), 4);
+        assert_eq!(&buf[..4], b"hell");
+        tx.write_all(b"rld").unwrap();
+        drop(tx);
+        let mut rest = Vec::new();
+        rx.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"o world");
+
+        let (mut tx, rx) = new(8);
+        drop(rx);
+        assert_eq!(tx.write(b"x").unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn io_vectored() {
+        use std::io::{ErrorKind, IoSlice, IoSliceMut, Read, Write};
+
+        let (mut tx, mut rx) = new(8);
+        tx.write_all(b"xyz").unwrap();
+        rx.read_exact(&mut [0; 3]).unwrap();
+        // Wraps around the end of the buffer, and the last slice only fits
+        // in part.
+        let bufs = [
+            IoSlice::new(b"ab"),
+            IoSlice::new(b""),
+            IoSlice::new(b"cdefgh"),
+        ];
+        assert_eq!(tx.write_vectored(&bufs).unwrap(), 8);
+        assert_eq!(
+            tx.write_vectored(&[IoSlice::new(b"i")]).unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+
+        let (mut a, mut b, mut c) = ([0; 3], [0; 2], [0; 5]);
+        let mut bufs = [
+            IoSliceMut::new(&mut a),
+            IoSliceMut::new(&mut b),
+            IoSliceMut::new(&mut c),
+        ];
+        assert_eq!(rx.read_vectored(&mut bufs).unwrap(), 8);
+        assert_eq!((&a, &b, &c[..3]), (b"abc", b"de", &b"fgh"[..]));
+        assert_eq!(
+            rx.read_vectored(&mut [IoSliceMut::new(&mut a)])
+                .unwrap_err()
+                .kind(),
+            ErrorKind::WouldBlock
+        );
+        drop(tx);
+        assert_eq!(rx.read_vectored(&mut [IoSliceMut::new(&mut a)]).unwrap(), 0);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn poll_recv() {
+        use std::task::{Context, Poll, Waker};
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let (mut tx, mut rx) = new::<u32>(1);
+        assert_eq!(rx.poll_recv(&mut cx), Poll::Pending);
+        let mut el = Some(1);
+        assert_eq!(tx.poll_send(&mut cx, &mut el), Poll::Ready(Ok(())));
+        assert_eq!(el, None);
+        // Full, so the element stays with the caller.
+        let mut el = Some(2);
+        assert_eq!(tx.poll_send(&mut cx, &mut el), Poll::Pending);
+        assert_eq!(el, Some(2));
+        assert_eq!(rx.poll_recv(&mut cx), Poll::Ready(Some(1)));
+        assert_eq!(tx.poll_send(&mut cx, &mut el), Poll::Ready(Ok(())));
+        drop(tx);
+        assert_eq!(rx.poll_recv(&mut cx), Poll::Ready(Some(2)));
+        assert_eq!(rx.poll_recv(&mut cx), Poll::Ready(None));
+    }
+
+    #[test]
+    fn try_new() {
+        use crate::spsc::try_new;
+
+        assert!(try_new::<u64>(usize::MAX / 4).is_err());
+        let (mut tx, mut rx) = try_new(2).unwrap();
+        tx.try_send(1).unwrap();
+        assert_eq!(rx.try_recv(), Ok(1));
+    }
+
+    #[test]
+    fn alloc_failure() {
+        use std::{
+            alloc::{AllocError, Allocator, Layout},
+            ptr::NonNull,
+        };
+
+        use crate::spsc::try_new_in;
+
+        struct Failing;
+
+        unsafe impl Allocator for Failing {
+            fn allocate(&self, _: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                Err(AllocError)
+            }
+
+            unsafe fn deallocate(&self, _: NonNull<u8>, _: Layout) {
+                unreachable!("nothing was allocated");
+            }
+        }
+
+        assert!(try_new_in::<String, _>(4, Failing).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn scoped() {
+        use std::mem::MaybeUninit;
+
+        use crate::spsc::{Scoped, scoped, try_new_in};
+
+        const N: usize = if cfg!(miri) { 100 } else { 10_000 };
+        let mut storage = [MaybeUninit::uninit(); 1024];
+        assert!(Scoped::size_for::<String>(8) <= storage.len());
+        let (mut tx, mut rx) = scoped(8, &mut storage);
+        thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..N {
+                    tx.send(i.to_string()).unwrap();
+                }
+            });
+            for i in 0..N {
+                assert_eq!(rx.recv().unwrap(), i.to_string());
+            }
+        });
+        drop(rx);
+        // Nothing holds on to the storage once both ends are gone.
+        let (mut tx, rx) = scoped::<u8>(4, &mut storage);
+        tx.try_send(1).unwrap();
+        drop((tx, rx));
+
+        let mut tiny = [MaybeUninit::uninit(); 16];
+        assert!(try_new_in::<u64, _>(64, Scoped::new(&mut tiny)).is_err());
+    }
+
+    #[test]
+    fn send_checked() {
+        let (mut tx, mut rx) = new(3);
+        for i in 0..3 {
+            tx.try_send_checked(i).unwrap();
+        }
+        assert_eq!(tx.try_send_checked(3), Err((3, 0)));
+        assert_eq!(rx.recv_many(2), [0, 1]);
+        tx.try_send_checked(3).unwrap();
+        drop(rx);
+        assert_eq!(tx.try_send_checked(4), Err((4, 1)));
+    }
+
+    #[test]
+    fn send_indexed() {
+        let (mut tx, mut rx) = new(2);
+        assert_eq!(tx.try_send_indexed('a'), Ok(0));
+        assert_eq!(tx.try_send_indexed('b'), Ok(1));
+        assert_eq!(tx.try_send_indexed('c'), Err(TrySendError::Full('c')));
+        assert_eq!(rx.try_recv(), Ok('a'));
+        assert_eq!(tx.try_send_indexed('c'), Ok(2));
+    }
+
+    #[test]
+    fn ack() {
+        let (mut tx, mut rx) = new(8);
+        for i in 0..5 {
+            tx.try_send(i).unwrap();
+        }
+        assert_eq!(rx.recv_many(5), [0, 1, 2, 3, 4]);
+        assert_eq!(tx.acked_position(), 0);
+        rx.ack(2);
+        let (head, _) = tx.debug_positions();
+        // Everything was received, but only two elements processed.
+        assert_eq!(head - tx.acked_position(), 3);
+        rx.ack(5);
+        assert_eq!(tx.acked_position(), head);
+    }
+
+    #[test]
+    #[should_panic = "acknowledged position out of range"]
+    fn ack_unreceived() {
+        let (mut tx, mut rx) = new(8);
+        tx.try_send(1).unwrap();
+        rx.ack(1);
+    }
 
     #[test]
-    fn create() {
-        let (mut tx, mut rx) = new::<u32>(10);
-        tx.try_send(10);
-        tx.try_send(20);
-        assert_eq!(rx.try_recv(), Some(10));
-        assert_eq!(rx.try_recv(), Some(20));
+    fn shrink_to() {
+        let (mut tx, mut rx) = new(64);
+        for i in 0..64 {
+            tx.try_send(i).unwrap();
+        }
+        for i in 0..61 {
+            assert_eq!(rx.try_recv(), Ok(i));
+        }
+        rx.shrink_to(4);
+        // What is still queued stays in the old buffer, which takes more
+        // until it has been emptied.
+        tx.try_send(64).unwrap();
+        assert_eq!(tx.capacity(), 64);
+        assert_eq!(rx.recv_many(8), [61, 62, 63, 64]);
+        assert_eq!(tx.try_send_indexed(65), Ok(65));
+        assert_eq!(tx.capacity(), 4);
+        for i in 66..69 {
+            tx.try_send(i).unwrap();
+        }
+        assert_eq!(tx.try_send(69), Err(TrySendError::Full(69)));
+        assert!(rx.is_full());
+        assert_eq!(rx.debug_positions(), (69, 65));
+        assert_eq!(rx.capacity(), 64);
+        assert_eq!(rx.try_recv(), Ok(65));
+        assert_eq!(rx.capacity(), 4);
+        // Keeps going round the smaller buffer.
+        for i in 69..200 {
+            tx.try_send(i).unwrap();
+            assert_eq!(rx.try_recv(), Ok(i - 3));
+        }
+        assert_eq!(rx.take_all(), [197, 198, 199]);
+        #[cfg(feature = "metrics")]
+        {
+            let stats = rx.stats();
+            assert_eq!((stats.total_sent, stats.total_received), (200, 200));
+        }
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn shrink_to_pending() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        for rx_first in [false, true] {
+            let (mut tx, mut rx) = new(4);
+            tx.try_send(DropCounter(drops.clone())).unwrap();
+            rx.shrink_to(2);
+            // Already pending, so this one is dropped.
+            rx.shrink_to(1);
+            tx.try_send(DropCounter(drops.clone())).unwrap();
+            // The sender never got to move, and both buffers go.
+            if rx_first {
+                drop(rx);
+                assert!(tx.try_send(DropCounter(drops.clone())).is_err());
+                drop(tx);
+            } else {
+                drop(tx);
+                drop(rx);
+            }
+        }
+        assert_eq!(drops.load(Ordering::Relaxed), 5);
+
+        let (tx, mut rx) = new::<u8>(4);
+        drop(tx);
+        rx.shrink_to(2);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn shrink_to_moved() {
+        // The receiver goes before it came over to the new buffer.
+        let (mut tx, mut rx) = new(4);
+        rx.shrink_to(2);
+        tx.try_send(String::from("moved")).unwrap();
+        drop(rx);
+        assert!(tx.try_send(String::new()).is_err());
+        // Then the other way round, with the receiver asking again.
+        let (mut tx, mut rx) = new(4);
+        rx.shrink_to(2);
+        tx.try_send(String::from("moved")).unwrap();
+        rx.shrink_to(1);
+        drop(tx);
+        assert_eq!(rx.try_recv().as_deref(), Ok("moved"));
+        assert_eq!(rx.capacity(), 2);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn shrink_to_ack() {
+        let (mut tx, mut rx) = new(8);
+        for i in 0..5 {
+            tx.try_send(i).unwrap();
+        }
+        assert_eq!(rx.recv_many(5), [0, 1, 2, 3, 4]);
+        rx.ack(2);
+        rx.shrink_to(3);
+        tx.begin_shutdown();
+        rx.ack_shutdown();
+        tx.try_send(5).unwrap();
+        // All of it came along to the new buffer.
+        assert_eq!(tx.capacity(), 3);
+        assert_eq!(tx.acked_position(), 2);
+        assert!(rx.is_draining());
+        let state = unsafe { tx.ptr.as_ref() }
+            .meta
+            .state
+            .load(Ordering::Relaxed);
+        assert_ne!(state & SHUTDOWN_ACKED, 0);
+        rx.ack(5);
+        assert_eq!(tx.acked_position(), 5);
+        assert_eq!(rx.try_recv(), Ok(5));
+    }
+
+    #[test]
+    #[should_panic = "part of its handles' type"]
+    fn shrink_to_pow2() {
+        let (_tx, mut rx) = crate::spsc::new_const::<4, u8>();
+        rx.shrink_to(2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn shrink_to_threads() {
+        const N: usize = if cfg!(miri) { 400 } else { 20_000 };
+        let (mut tx, mut rx) = new(64);
+        let producer = thread::spawn(move || {
+            for i in 0..N {
+                tx.send(i).unwrap();
+            }
+        });
+        for i in 0..N {
+            // Shrinking, and growing back, while the sender keeps going.
+            if i % (N / 20) == 0 {
+                rx.shrink_to([3, 64, 8, 1][i / (N / 20) % 4]);
+            }
+            assert_eq!(rx.recv(), Ok(i));
+        }
+        producer.join().unwrap();
+        assert_eq!(rx.recv(), Err(RecvError));
+    }
+
+    #[cfg(feature = "heartbeat")]
+    #[test]
+    fn idle_for() {
+        let (mut tx, mut rx) = new(2);
+        tx.try_send(1).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        assert!(rx.idle_for() >= Duration::from_millis(20));
+        rx.try_recv().unwrap();
+        assert!(rx.idle_for() < Duration::from_millis(20));
+    }
+
+    #[test]
+    fn undrop_receiver() {
+        let (mut tx, mut rx) = new(4);
+        assert!(tx.undrop_receiver().is_none());
+        for i in 0..3 {
+            tx.try_send(i).unwrap();
+        }
+        assert_eq!(rx.try_recv(), Ok(0));
+        drop(rx);
+        assert_eq!(tx.try_send(3), Err(TrySendError::Disconnected(3)));
+
+        let mut rx = tx.undrop_receiver().unwrap();
+        assert!(tx.undrop_receiver().is_none());
+        tx.try_send(3).unwrap();
+        assert_eq!(rx.recv_many(4), [1, 2, 3]);
+    }
+
+    #[test]
+    fn size_hint() {
+        let (mut tx, mut rx) = new(8);
+        for i in 0..5 {
+            tx.try_send(i).unwrap();
+        }
+        assert_eq!(rx.try_iter().size_hint(), (5, None));
+
+        let drain = rx.drain();
+        tx.try_send(5).unwrap();
+        // The element sent after draining started isn't part of it.
+        assert_eq!(drain.len(), 5);
+        let mut got = Vec::with_capacity(drain.len());
+        let buf = got.as_ptr();
+        got.extend(drain);
+        assert_eq!(got, [0, 1, 2, 3, 4]);
+        assert_eq!(got.as_ptr(), buf);
+
+        tx.try_send(6).unwrap();
+        let mut drain = rx.drain();
+        assert_eq!(drain.next(), Some(5));
+        drop(drain);
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn send_with() {
+        use std::cell::Cell;
+
+        let (mut tx, mut rx) = new(2);
+        let calls = Cell::new(0);
+        let build = |i: u32| {
+            let calls = &calls;
+            move || {
+                calls.set(calls.get() + 1);
+                i
+            }
+        };
+        assert_eq!(tx.try_send_with(build(1)), Ok(()));
+        assert_eq!(tx.try_send_with(build(2)), Ok(()));
+        assert_eq!(tx.try_send_with(build(3)), Err(TrySendError::Full(())));
+        #[cfg(feature = "metrics")]
+        assert!(tx.stats().send_full_rejections == 1 && tx.last_send_blocked());
+        assert_eq!(rx.recv_many(2), [1, 2]);
+        drop(rx);
+        assert_eq!(
+            tx.try_send_with(build(4)),
+            Err(TrySendError::Disconnected(()))
+        );
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    #[cfg_attr(miri, ignore = "measures wall-clock time")]
+    fn recv_batch_until() {
+        let (mut tx, mut rx) = new(8);
+        let producer = thread::spawn(move || {
+            for i in 0..3 {
+                tx.send(i).unwrap();
+                thread::sleep(Duration::from_millis(10));
+            }
+            // Well past the deadline below.
+            thread::sleep(Duration::from_millis(200));
+            tx.send(3).unwrap();
+        });
+        let deadline = Instant::now() + Duration::from_millis(100);
+        assert_eq!(rx.recv_batch_until(10, deadline), [0, 1, 2]);
+        assert!(Instant::now() >= deadline);
+        assert_eq!(
+            rx.recv_batch_until(1, deadline + Duration::from_secs(10)),
+            [3]
+        );
+        producer.join().unwrap();
+        assert!(rx.recv_batch_until(1, Instant::now()).is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn blocking_iter() {
+        let (mut tx, mut rx) = new(2);
+        let producer = thread::spawn(move || {
+            for i in 0..10 {
+                tx.send(i).unwrap();
+                if i % 3 == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        });
+        let mut iter = rx.blocking_iter();
+        let mut expected = 0;
+        while let Some(&peeked) = iter.peek() {
+            assert_eq!(peeked, expected);
+            assert_eq!(iter.next(), Some(peeked));
+            expected += 1;
+        }
+        assert_eq!(expected, 10);
+        assert_eq!(iter.next(), None);
+        producer.join().unwrap();
+    }
+
+    #[test]
+    fn recv_into() {
+        use std::mem::MaybeUninit;
+
+        let (mut tx, mut rx) = new(2);
+        let mut dst = MaybeUninit::new(String::from("untouched"));
+        assert!(!rx.try_recv_into(&mut dst));
+        assert_eq!(unsafe { dst.assume_init_ref() }, "untouched");
+        unsafe { dst.assume_init_drop() };
+
+        tx.try_send(String::from("hello")).unwrap();
+        assert!(rx.try_recv_into(&mut dst));
+        assert_eq!(unsafe { dst.assume_init() }, "hello");
+        assert!(rx.is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn recv_yielding() {
+        let (mut tx, mut rx) = new(2);
+        assert_eq!(rx.try_recv_yielding(), Err(TryRecvError::Empty));
+        tx.try_send(1).unwrap();
+        assert_eq!(rx.try_recv_yielding(), Ok(1));
+        drop(tx);
+        assert_eq!(rx.try_recv_yielding(), Err(TryRecvError::Disconnected));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn take_all() {
+        const N: usize = if cfg!(miri) { 200 } else { 10_000 };
+        let (mut tx, mut rx) = new(16);
+        assert!(rx.take_all().is_empty());
+        let producer = thread::spawn(move || {
+            for i in 0..N {
+                let mut el = i.to_string();
+                while let Err(e) = tx.try_send(el) {
+                    el = e.into_inner();
+                    thread::yield_now();
+                }
+            }
+        });
+        let mut next = 0;
+        while next < N {
+            let batch = rx.take_all();
+            assert!(batch.len() <= 16);
+            if batch.is_empty() {
+                thread::yield_now();
+            }
+            for el in batch {
+                assert_eq!(el, next.to_string());
+                next += 1;
+            }
+        }
+        producer.join().unwrap();
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn extend() {
+        let (mut tx, mut rx) = new(4);
+        tx.extend(0..10);
+        assert_eq!(rx.len(), 4);
+        assert_eq!(rx.recv_many(10), [0, 1, 2, 3]);
+        tx.extend([4]);
+        assert_eq!(rx.try_recv(), Ok(4));
+    }
+
+    #[test]
+    fn from_iter() {
+        use crate::spsc::from_iter;
+
+        let (_tx, mut rx) = from_iter(0..5);
+        assert_eq!(rx.capacity(), 5);
+        assert_eq!(rx.recv_many(10), [0, 1, 2, 3, 4]);
+
+        // No exact length to go by.
+        let (_tx, mut rx) = from_iter((0..10).filter(|i| i % 3 == 0));
+        assert_eq!(rx.capacity(), 4);
+        assert_eq!(rx.recv_many(10), [0, 3, 6, 9]);
+
+        let (mut tx, rx) = from_iter(std::iter::empty());
+        assert!(rx.is_empty());
+        tx.try_send(1).unwrap();
+
+        // Claims an exact length that is too short.
+        struct Liar(std::ops::Range<u32>);
+        impl Iterator for Liar {
+            type Item = u32;
+            fn next(&mut self) -> Option<u32> {
+                self.0.next()
+            }
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (2, Some(2))
+            }
+        }
+        let (_tx, mut rx) = from_iter(Liar(0..5));
+        assert_eq!(rx.capacity(), 5);
+        assert_eq!(rx.recv_many(10), [0, 1, 2, 3, 4]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn publish_delay() {
+        use crate::spsc::PUBLISH_DELAY;
+
+        const N: usize = if cfg!(miri) { 100 } else { 10_000 };
+        // Elements that can't be received half-written without noticing.
+        fn element(i: usize) -> Box<[usize; 8]> {
+            Box::new([i; 8])
+        }
+
+        PUBLISH_DELAY.store(50, Ordering::Relaxed);
+        let (mut tx, mut rx) = new(4);
+        let producer = thread::spawn(move || {
+            for i in 0..N {
+                tx.send(element(i)).unwrap();
+                if i % 100 == 0 {
+                    tx.send_iter((0..3).map(|_| element(i)));
+                }
+            }
+        });
+        let mut last = 0;
+        // Polls rather than blocks, since parking and waking synchronize on
+        // their own and would hide a missing `Release` on `head`.
+        loop {
+            match rx.try_recv() {
+                Ok(el) => {
+                    // Whatever `head` lets us see must be fully written, and
+                    // in order.
+                    assert!(el.iter().all(|&x| x == el[0]));
+                    assert!(el[0] >= last);
+                    last = el[0];
+                }
+                Err(TryRecvError::Empty) => thread::yield_now(),
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        producer.join().unwrap();
+        PUBLISH_DELAY.store(0, Ordering::Relaxed);
+        assert_eq!(last, N - 1);
+    }
+
+    #[test]
+    fn recv_unchecked() {
+        let (mut tx, mut rx) = new(8);
+        tx.send_iter((0..5).map(|i| i.to_string()));
+        let n = rx.len();
+        let got: Vec<_> = (0..n).map(|_| unsafe { rx.try_recv_unchecked() }).collect();
+        assert_eq!(got, ["0", "1", "2", "3", "4"]);
+        assert!(rx.is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn send_all() {
+        let data: Vec<u8> = (0..10_000).map(|i| (i * 31 % 251) as u8).collect();
+        let (mut tx, mut rx) = new(64);
+        let consumer = thread::spawn(move || {
+            let mut got = Vec::new();
+            let mut buf = [0; 48];
+            loop {
+                match rx.recv() {
+                    Ok(b) => got.push(b),
+                    Err(_) => return got,
+                }
+                let n = rx.try_recv_slice(&mut buf);
+                got.extend_from_slice(&buf[..n]);
+            }
+        });
+        tx.send_all(&data).unwrap();
+        drop(tx);
+        assert_eq!(consumer.join().unwrap(), data);
+
+        let (mut tx, rx) = new(4);
+        let dropper = thread::spawn(move || drop(rx));
+        assert_eq!(tx.send_all(&[0u8; 100]), Err(SendError(())));
+        dropper.join().unwrap();
+    }
+
+    #[test]
+    fn is_terminated() {
+        let (mut tx, mut rx) = new(4);
+        tx.try_send(1).unwrap();
+        assert!(!rx.is_terminated());
+        drop(tx);
+        // Still holding an element.
+        assert!(!rx.is_terminated());
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert!(rx.is_terminated());
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+
+        let (tx, rx) = new::<u8>(4);
+        assert!(!rx.is_terminated());
+        tx.close();
+        assert!(rx.is_terminated());
+    }
+
+    #[test]
+    fn to_vec() {
+        let (mut tx, mut rx) = new(4);
+        tx.send_iter(["a", "b", "c"].map(String::from));
+        assert_eq!(rx.recv_many(2), ["a", "b"]);
+        // Wraps around the end of the buffer.
+        tx.send_iter(["d", "e", "f"].map(String::from));
+        assert_eq!(rx.to_vec(), ["c", "d", "e", "f"]);
+        assert_eq!(rx.len(), 4);
+        assert_eq!(rx.recv_many(4), ["c", "d", "e", "f"]);
+        assert!(rx.to_vec().is_empty());
+    }
+
+    #[test]
+    fn backoff_callbacks() {
+        let (mut tx, mut rx) = new(2);
+        tx.send_iter([0, 1]);
+        let mut fulls = 0;
+        // Each time the queue is full, the receiver makes room for one more.
+        for i in 2..5 {
+            tx.send_with_backoff(i, || {
+                fulls += 1;
+                assert_eq!(rx.try_recv(), Ok(i - 2));
+            })
+            .unwrap();
+        }
+        assert_eq!(fulls, 3);
+
+        let mut empties = 0;
+        let mut tx = Some(tx);
+        let mut got = Vec::new();
+        while let Ok(el) = rx.recv_with_backoff(|| {
+            empties += 1;
+            // Hang up on the second wait.
+            if empties == 2 {
+                tx.take();
+            }
+        }) {
+            got.push(el);
+        }
+        assert_eq!(got, [3, 4]);
+        assert_eq!(empties, 2);
+    }
+
+    #[test]
+    fn byte_capacity() {
+        #[repr(align(64))]
+        struct Line {
+            _bytes: [u8; 3],
+        }
+
+        let (tx, _rx) = new::<u32>(10);
+        assert_eq!(tx.byte_capacity(), 40);
+        let (tx, _rx) = new::<()>(10);
+        assert_eq!(tx.byte_capacity(), 0);
+        // Rounded up to the alignment.
+        let (tx, _rx) = new::<Line>(10);
+        assert_eq!(tx.byte_capacity(), 640);
+    }
+
+    #[test]
+    fn peek_and_skip() {
+        let (mut tx, mut rx) = new(16);
+        // A 4-byte length prefix followed by the payload.
+        tx.try_send_slice(&[0, 0, 0, 3, b'a', b'b', b'c']);
+        let header = rx.peek_slice(4);
+        let len = u32::from_be_bytes(header.try_into().unwrap()) as usize;
+        rx.skip(4);
+        assert_eq!(rx.len(), len);
+        assert_eq!(rx.peek_slice(10), b"abc");
+        assert_eq!(rx.recv_many(len), b"abc");
+        assert!(rx.peek_slice(4).is_empty());
+    }
+
+    #[test]
+    fn skip_drops() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut tx, mut rx) = new(4);
+        for _ in 0..3 {
+            tx.try_send(DropCounter(drops.clone())).unwrap();
+        }
+        rx.skip(2);
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+        assert_eq!(rx.len(), 1);
+        rx.clear();
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+        // Dropped elements count as received.
+        #[cfg(feature = "metrics")]
+        assert_eq!(rx.stats().total_received, 3);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn skip_panic_wakes_sender() {
+        struct Bomb(bool);
+
+        impl Drop for Bomb {
+            fn drop(&mut self) {
+                if self.0 {
+                    std::panic!("failed to drop the element");
+                }
+            }
+        }
+
+        let (mut tx, mut rx) = new(1);
+        tx.try_send(Bomb(true)).unwrap();
+        let producer = thread::spawn(move || tx.send(Bomb(false)).is_ok());
+        thread::sleep(Duration::from_millis(20));
+        let skipped = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| rx.skip(1)));
+        assert!(skipped.is_err());
+        // The slot was handed back and the parked sender told about it.
+        assert!(producer.join().unwrap());
+        assert!(!rx.try_recv().unwrap().0);
+    }
+
+    #[test]
+    #[should_panic = "more elements than are queued"]
+    fn skip_past_end() {
+        let (mut tx, mut rx) = new(4);
+        tx.try_send(1).unwrap();
+        rx.skip(2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn wait_for_space() {
+        let (mut tx, mut rx) = new(8);
+        tx.send_iter(0..8);
+        assert_eq!(tx.wait_for_space(0), Ok(0));
+        let consumer = thread::spawn(move || {
+            for i in 0..5 {
+                assert_eq!(rx.recv(), Ok(i));
+                thread::sleep(Duration::from_millis(1));
+            }
+            rx
+        });
+        assert!(tx.wait_for_space(5).unwrap() >= 5);
+        let rx = consumer.join().unwrap();
+        assert_eq!(tx.try_send_slice(&[8, 9, 10, 11, 12]), 5);
+        drop(rx);
+        assert_eq!(tx.wait_for_space(1), Err(SendError(())));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn flush() {
+        let (mut tx, mut rx) = new(8);
+        assert_eq!(tx.flush(), Ok(()));
+        tx.send_iter(0..5);
+        let received = Arc::new(AtomicUsize::new(0));
+        let consumer = thread::spawn({
+            let received = received.clone();
+            move || {
+                for i in 0..5 {
+                    thread::sleep(Duration::from_millis(5));
+                    assert_eq!(rx.recv(), Ok(i));
+                    received.fetch_add(1, Ordering::Relaxed);
+                }
+                rx
+            }
+        });
+        assert_eq!(tx.flush(), Ok(()));
+        // The receiver may not have counted the last one yet, but it has
+        // taken it out.
+        let rx = consumer.join().unwrap();
+        assert_eq!(received.load(Ordering::Relaxed), 5);
+
+        tx.try_send(5).unwrap();
+        drop(rx);
+        assert_eq!(tx.flush(), Err(SendError(())));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn throughput() {
+        use crate::spsc::new_for_throughput;
+
+        let (tx, _rx) = new_for_throughput::<u8>(1000, Duration::from_millis(10));
+        assert_eq!(tx.capacity(), 16);
+        // A partial element still needs a slot.
+        let (tx, _rx) = new_for_throughput::<u8>(1000, Duration::from_micros(8500));
+        assert_eq!(tx.capacity(), 16);
+        let (tx, _rx) = new_for_throughput::<u8>(0, Duration::from_secs(1));
+        assert_eq!(tx.capacity(), 1);
+    }
+
+    #[test]
+    fn new_const() {
+        use crate::spsc::new_const;
+
+        const N: u64 = if cfg!(miri) { 64 } else { 1024 };
+        let (mut tx, mut rx) = new_const::<{ N as usize }, u64>();
+        assert_eq!(tx.capacity(), N as usize);
+        // Two and a half laps, a batch at a time.
+        let mut next = 0;
+        for batch in (0..N * 5 / 2)
+            .collect::<Vec<_>>()
+            .chunks(N as usize / 2 + 1)
+        {
+            tx.send_iter(batch.iter().copied());
+            for el in rx.try_iter() {
+                assert_eq!(el, next);
+                next += 1;
+            }
+        }
+        assert_eq!(next, N * 5 / 2);
+    }
+
+    #[test]
+    fn high_water() {
+        let (mut tx, rx) = new(8);
+        let mut seen = Vec::new();
+        let mut rx = rx.with_high_water(3, |len| seen.push(len));
+        tx.send_iter(0..5);
+        for i in 0..3 {
+            assert_eq!(rx.try_recv(), Ok(i));
+        }
+        // Back under the mark, then over it again.
+        tx.send_iter(5..8);
+        while rx.try_recv().is_ok() {}
+        drop(rx);
+        assert_eq!(seen, [5, 5]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn channel() {
+        use crate::spsc::Channel;
+
+        let chan = Channel::new(4);
+        assert_eq!((chan.capacity(), chan.len()), (4, 0));
+        let (mut tx, mut rx) = chan.split();
+        let producer = thread::spawn(move || {
+            for i in 0..10 {
+                tx.send(i).unwrap();
+            }
+        });
+        assert!(rx.blocking_iter().eq(0..10));
+        producer.join().unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn recv_adaptive() {
+        let (mut tx, mut rx) = new(128);
+        let consumer = thread::spawn(move || {
+            let burst = rx.recv_adaptive();
+            let single = rx.recv_adaptive();
+            (burst.len(), single, rx.recv_adaptive())
+        });
+        thread::sleep(Duration::from_millis(20));
+        // All 100 show up at once.
+        assert_eq!(tx.send_iter(0..100), 100);
+        // Only send the next one once the burst has been taken.
+        while !tx.is_empty() {
+            thread::yield_now();
+        }
+        tx.send(100).unwrap();
+        drop(tx);
+        let (burst, single, rest) = consumer.join().unwrap();
+        assert_eq!(burst, 100);
+        assert_eq!(single, [100]);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn len_checked() {
+        let (mut tx, mut rx) = new::<u32>(4);
+        seed(&mut tx, &mut rx, usize::MAX - 1);
+        tx.send_iter(0..3);
+        assert_eq!((tx.len_checked(), rx.len_checked()), (Some(3), Some(3)));
+
+        // Pretend the sender ran two slots past the tail.
+        let meta = unsafe { &tx.ptr.as_ref().meta };
+        let (head, tail) = tx.debug_positions();
+        meta.head.store(tail.wrapping_add(6), Ordering::Relaxed);
+        assert_eq!((tx.len_checked(), rx.len_checked()), (None, None));
+        // Put it back so the queue can be dropped.
+        meta.head.store(head, Ordering::Relaxed);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [0, 1, 2]);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn opt_shims() {
+        let (mut tx, mut rx) = new(1);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(rx.try_recv_opt(), None);
+        assert_eq!(tx.try_send_opt(1), None);
+        assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+        assert_eq!(tx.try_send_opt(2), Some(2));
+        assert_eq!(rx.try_recv_opt(), Some(1));
+        tx.try_send(3).unwrap();
+        assert_eq!(rx.try_recv(), Ok(3));
+        drop(rx);
+        assert_eq!(tx.try_send(4), Err(TrySendError::Disconnected(4)));
+        assert_eq!(tx.try_send_opt(4), Some(4));
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Send(u8),
+        SendMany(u8),
+        Recv,
+        Skip(u8),
+    }
+
+    fn op() -> impl proptest::strategy::Strategy<Value = Op> {
+        use proptest::prelude::*;
+
+        prop_oneof![
+            any::<u8>().prop_map(Op::Send),
+            (0..8u8).prop_map(Op::SendMany),
+            Just(Op::Recv),
+            (0..8u8).prop_map(Op::Skip),
+        ]
+    }
+
+    fn proptest_config() -> proptest::test_runner::Config {
+        proptest::test_runner::Config {
+            cases: if cfg!(miri) { 4 } else { 256 },
+            // Miri can't touch the file system.
+            failure_persistence: None,
+            ..Default::default()
+        }
+    }
+
+    proptest::proptest! {
+        #![proptest_config(proptest_config())]
+
+        #[test]
+        fn invariants(
+            cap in 1..10usize,
+            start in proptest::prelude::any::<bool>(),
+            ops in proptest::collection::vec(op(), 0..100),
+        ) {
+            let (mut tx, mut rx) = new::<u8>(cap);
+            if start {
+                // Start right before the positions wrap.
+                let wrap = unsafe { tx.ptr.as_ref() }.meta.wrap;
+                seed(&mut tx, &mut rx, wrap.wrapping_sub(3));
+            }
+            for op in ops {
+                match op {
+                    Op::Send(el) => drop(tx.try_send(el)),
+                    Op::SendMany(n) => drop(tx.send_iter(0..n)),
+                    Op::Recv => drop(rx.try_recv()),
+                    Op::Skip(n) => rx.skip(usize::from(n).min(rx.len())),
+                }
+                tx.assert_invariants();
+                rx.assert_invariants();
+            }
+        }
+
+        #[test]
+        fn matches_model(
+            cap in 1..10usize,
+            start in proptest::prelude::any::<bool>(),
+            ops in proptest::collection::vec(op(), 0..100),
+        ) {
+            use std::collections::VecDeque;
+
+            use proptest::{prop_assert, prop_assert_eq};
+
+            let (mut tx, mut rx) = new::<u8>(cap);
+            if start {
+                let wrap = unsafe { tx.ptr.as_ref() }.meta.wrap;
+                seed(&mut tx, &mut rx, wrap.wrapping_sub(3));
+            }
+            let mut model = VecDeque::with_capacity(cap);
+            for op in ops {
+                match op {
+                    Op::Send(el) => {
+                        let expected = if model.len() < cap {
+                            model.push_back(el);
+                            Ok(())
+                        } else {
+                            Err(TrySendError::Full(el))
+                        };
+                        prop_assert_eq!(tx.try_send(el), expected);
+                    }
+                    Op::SendMany(n) => {
+                        let fits = usize::from(n).min(cap - model.len());
+                        model.extend(0..fits as u8);
+                        prop_assert_eq!(tx.send_iter(0..n), fits);
+                    }
+                    Op::Recv => {
+                        let expected = model.pop_front().ok_or(TryRecvError::Empty);
+                        prop_assert_eq!(rx.try_recv(), expected);
+                    }
+                    Op::Skip(n) => {
+                        let n = usize::from(n).min(model.len());
+                        model.drain(..n);
+                        rx.skip(n);
+                    }
+                }
+                prop_assert_eq!(tx.len(), model.len());
+                prop_assert_eq!(rx.peek(), model.front());
+            }
+            prop_assert!(rx.try_iter().eq(model));
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn wait_nonempty() {
+        let (mut tx, mut rx) = new(4);
+        let producer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            tx.send(String::from("a")).unwrap();
+        });
+        assert_eq!(rx.wait_nonempty(), Ok(()));
+        // Still there, and still the first in line.
+        assert_eq!(rx.peek().map(String::as_str), Some("a"));
+        assert_eq!(rx.wait_nonempty(), Ok(()));
+        producer.join().unwrap();
+        assert_eq!(rx.try_recv().as_deref(), Ok("a"));
+        assert_eq!(rx.wait_nonempty(), Err(RecvError));
+    }
+
+    #[test]
+    fn recv_or_default() {
+        let (mut tx, mut rx) = new::<[f32; 2]>(4);
+        tx.try_send([0.5, -0.5]).unwrap();
+        tx.try_send([1.0, 0.0]).unwrap();
+        assert_eq!(rx.try_recv_or_default(), [0.5, -0.5]);
+        assert_eq!(rx.try_recv_or_default(), [1.0, 0.0]);
+        // Silence once drained, whether or not the sender is still there.
+        assert_eq!(rx.try_recv_or_default(), [0.0; 2]);
+        drop(tx);
+        assert_eq!(rx.try_recv_or_default(), [0.0; 2]);
+        assert!(rx.is_terminated());
+    }
+
+    #[cfg(all(feature = "metrics", feature = "std"))]
+    #[test]
+    #[cfg_attr(miri, ignore = "measures wall-clock time")]
+    fn recv_throughput() {
+        let (mut tx, mut rx) = new(32);
+        tx.send_iter(0..20);
+        assert_eq!(rx.throughput(Duration::from_secs(1)), 0.0);
+        // One every 10ms, so about 100 a second. Sleeping only ever makes
+        // it slower.
+        for _ in 0..20 {
+            rx.try_recv().unwrap();
+            thread::sleep(Duration::from_millis(10));
+        }
+        let rate = rx.throughput(Duration::from_secs(10));
+        assert!((40.0..=100.0).contains(&rate), "{rate}");
+        // Eventually nothing is left in the window.
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(rx.throughput(Duration::from_millis(20)), 0.0);
+    }
+
+    #[test]
+    fn send_keep() {
+        use crate::spsc::SendStatus;
+
+        let (mut tx, mut rx) = new(1);
+        tx.try_send([0u8; 4096]).unwrap();
+        let mut el = Some([7u8; 4096]);
+        let addr = el.as_ref().unwrap().as_ptr();
+        for _ in 0..3 {
+            assert_eq!(tx.try_send_keep(&mut el), SendStatus::Full);
+            // Still the same value in the same place.
+            let kept = el.as_ref().unwrap();
+            assert_eq!((kept.as_ptr(), kept[4095]), (addr, 7));
+        }
+        assert_eq!(rx.try_recv().unwrap()[0], 0);
+        assert_eq!(tx.try_send_keep(&mut el), SendStatus::Sent);
+        assert!(el.is_none());
+        assert_eq!(rx.try_recv().unwrap()[0], 7);
+
+        let mut el = Some([1u8; 4096]);
+        drop(rx);
+        assert_eq!(tx.try_send_keep(&mut el), SendStatus::Disconnected);
+        assert!(el.is_some());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn shutdown_handshake() {
+        const N: usize = if cfg!(miri) { 50 } else { 1000 };
+        let (mut tx, mut rx) = new(8);
+        let consumer = thread::spawn(move || {
+            let mut got = Vec::new();
+            loop {
+                match rx.recv_timeout(Duration::from_millis(1)) {
+                    Ok(el) => got.push(el),
+                    // Only stop once asked to and nothing is left.
+                    Err(RecvTimeoutError::Timeout) if rx.is_draining() && rx.is_empty() => break,
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => unreachable!(),
+                }
+            }
+            rx.ack_shutdown();
+            // The receiver is still around after acknowledging, and only
+            // now sees the stream end.
+            assert_eq!(rx.recv(), Err(RecvError));
+            assert!(rx.is_closed());
+            got
+        });
+        for i in 0..N {
+            tx.send(i).unwrap();
+        }
+        tx.begin_shutdown();
+        assert_eq!(tx.finish_shutdown(), Ok(()));
+        assert!(consumer.join().unwrap().into_iter().eq(0..N));
+
+        // Going away without acknowledging fails the handshake.
+        let (tx, rx) = new::<u8>(1);
+        let consumer = thread::spawn(move || {
+            while !rx.is_draining() {
+                thread::yield_now();
+            }
+        });
+        assert_eq!(tx.finish_shutdown(), Err(SendError(())));
+        consumer.join().unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn borrowed() {
+        let words = [String::from("a"), String::from("bb"), String::from("ccc")];
+        let (mut tx, mut rx) = new::<&str>(2);
+        let lens = thread::scope(|s| {
+            let consumer = s.spawn(move || rx.blocking_iter().map(str::len).collect::<Vec<_>>());
+            for word in &words {
+                tx.send(word.as_str()).unwrap();
+            }
+            drop(tx);
+            consumer.join().unwrap()
+        });
+        assert_eq!(lens, [1, 2, 3]);
+    }
+
+    #[test]
+    fn new_typed() {
+        use crate::spsc::{new_typed, pow2::Pow2Cap};
+
+        assert_eq!(Pow2Cap::new(0), None);
+        assert_eq!(Pow2Cap::new(6), None);
+        let cap = Pow2Cap::new(8).unwrap();
+        assert_eq!(cap.get(), 8);
+
+        let (mut tx, mut rx) = new_typed(cap);
+        assert!(tx.capacity() == 8 && unsafe { tx.ptr.as_ref() }.meta.mask == Some(7));
+        // Positions run past `usize::MAX` and the mask keeps the slots in
+        // order.
+        seed(&mut tx, &mut rx, usize::MAX - 3);
+        for lap in 0..3 {
+            tx.send_iter(lap * 8..lap * 8 + 8);
+            assert!(tx.is_full());
+            assert!(rx.try_iter().eq(lap * 8..lap * 8 + 8));
+        }
+        // And so do the handles' own fast paths.
+        for lap in 0..3 {
+            for el in lap * 8..lap * 8 + 8 {
+                tx.try_send(el).unwrap();
+            }
+            assert_eq!(tx.try_send(0), Err(TrySendError::Full(0)));
+            for el in lap * 8..lap * 8 + 8 {
+                assert_eq!(rx.try_recv(), Ok(el));
+            }
+            assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        }
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_test {
+    use loom::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        thread,
+    };
+
+    use crate::spsc::{TryRecvError, new};
+
+    #[test]
+    fn interleave() {
+        loom::model(|| {
+            let (mut tx, mut rx) = new::<u32>(2);
+            let producer = thread::spawn(move || {
+                for mut el in 0..3 {
+                    while let Err(e) = tx.try_send(el) {
+                        el = e.into_inner();
+                        thread::yield_now();
+                    }
+                }
+            });
+            let mut expected = 0;
+            while expected < 3 {
+                match rx.try_recv() {
+                    Ok(el) => {
+                        assert_eq!(el, expected);
+                        expected += 1;
+                    }
+                    Err(TryRecvError::Empty) => thread::yield_now(),
+                    Err(TryRecvError::Disconnected) => panic!("disconnected early"),
+                }
+            }
+            producer.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn drop_race() {
+        struct DropCounter(Arc<AtomicUsize>);
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        loom::model(|| {
+            let drops = Arc::new(AtomicUsize::new(0));
+            let (mut tx, rx) = new(1);
+            tx.try_send(DropCounter(drops.clone())).unwrap();
+            let dropper = thread::spawn(move || drop(tx));
+            drop(rx);
+            dropper.join().unwrap();
+            // The allocation, and with it the element, was freed exactly once.
+            assert_eq!(drops.load(Ordering::Relaxed), 1);
+        });
+    }
+
+    #[test]
+    fn no_lost_wakeup() {
+        use std::task::{Wake, Waker};
+
+        use loom::sync::atomic::AtomicBool;
+
+        struct Flag(AtomicBool);
+
+        impl Wake for Flag {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        loom::model(|| {
+            let (mut tx, mut rx) = new::<u32>(1);
+            let flag = std::sync::Arc::new(Flag(AtomicBool::new(false)));
+            let waker = Waker::from(flag.clone());
+            // Keep the sender alive so that only the send can wake us.
+            let producer = thread::spawn(move || {
+                tx.try_send(1).unwrap();
+                tx
+            });
+            // What a blocking receive does: check, register, check again and
+            // only then park.
+            let got = rx.try_recv().ok().or_else(|| {
+                unsafe { rx.ptr.as_ref() }.meta.rx_waker.register(&waker);
+                rx.try_recv().ok()
+            });
+            let tx = producer.join().unwrap();
+            // However the send lands around our checks, we either saw it or
+            // would be woken from the park.
+            assert!(got.is_some() || flag.0.load(Ordering::Relaxed));
+            drop(tx);
+        });
+    }
+
+    #[test]
+    fn close_race() {
+        loom::model(|| {
+            let (mut tx, mut rx) = new(1);
+            tx.try_send(Arc::new(())).unwrap();
+            let closer = thread::spawn(move || tx.close());
+            // Whatever the interleaving, the receiver either still sees the
+            // sender or sees a clean close, and gets the element first.
+            assert!(rx.try_recv().is_ok());
+            let connected = rx.is_connected();
+            assert!(connected || rx.is_closed());
+            drop(rx);
+            closer.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn shrink_to() {
+        loom::model(|| {
+            let (mut tx, mut rx) = new::<u32>(2);
+            tx.try_send(0).unwrap();
+            // The sender moves over for the second element whenever the
+            // receiver took the first in time, and stays put otherwise.
+            let producer = thread::spawn(move || {
+                tx.try_send(1).unwrap();
+            });
+            rx.shrink_to(1);
+            let mut expected = 0;
+            while expected < 2 {
+                match rx.try_recv() {
+                    Ok(el) => {
+                        assert_eq!(el, expected);
+                        expected += 1;
+                    }
+                    Err(TryRecvError::Empty) => thread::yield_now(),
+                    Err(TryRecvError::Disconnected) => panic!("disconnected early"),
+                }
+            }
+            producer.join().unwrap();
+            assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        });
+    }
+
+    #[test]
+    fn shrink_to_drop_race() {
+        loom::model(|| {
+            let el = Arc::new(());
+            let (mut tx, mut rx) = new(2);
+            rx.shrink_to(1);
+            // Whether the sender moves over before, during or after the
+            // receiver goes, both buffers and the element are freed.
+            let dropper = thread::spawn(move || {
+                let _ = tx.try_send(el);
+            });
+            drop(rx);
+            dropper.join().unwrap();
+        });
     }
 }
@@ -0,0 +1,225 @@
+//! Handles for queues whose capacity is a power of two as far as the type
+//! system is concerned.
+//!
+//! They wrap the handles from [`spsc`](super) and deref to them, so the
+//! whole API is still there. Only their own `try_send` and `try_recv`
+//! differ: they find slots by masking with a mask the handle carries, which
+//! for [`ConstCap`] is a constant, instead of going through the checks a
+//! queue of any capacity needs.
+
+use core::ops::{Deref, DerefMut};
+
+use super::{FIXED, RX, TryRecvError, TrySendError};
+use crate::sync::Ordering;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A capacity known to be a nonzero power of two, either when the queue is
+/// created or at compile time.
+///
+/// Implemented by [`ConstCap`] and [`Pow2Cap`] only, so a handle's capacity
+/// can be trusted.
+pub trait Capacity: Copy + sealed::Sealed {
+    /// Returns the capacity.
+    fn get(self) -> usize;
+}
+
+/// The capacity `N`, checked to be a power of two at compile time.
+///
+/// Handed out by [`new_const`](super::new_const).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConstCap<const N: usize>(());
+
+impl<const N: usize> ConstCap<N> {
+    pub(super) const fn new() -> Self {
+        const { assert!(N.is_power_of_two(), "queue capacity must be a power of two") };
+        ConstCap(())
+    }
+}
+
+impl<const N: usize> sealed::Sealed for ConstCap<N> {}
+
+impl<const N: usize> Capacity for ConstCap<N> {
+    #[inline]
+    fn get(self) -> usize {
+        N
+    }
+}
+
+/// A capacity that is known to be a nonzero power of two.
+///
+/// Taken by [`new_typed`](super::new_typed).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pow2Cap(usize);
+
+impl Pow2Cap {
+    /// Returns `Some` if `n` is a nonzero power of two.
+    pub const fn new(n: usize) -> Option<Self> {
+        if n.is_power_of_two() {
+            Some(Pow2Cap(n))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the capacity.
+    pub const fn get(self) -> usize {
+        self.0
+    }
+}
+
+impl sealed::Sealed for Pow2Cap {}
+
+impl Capacity for Pow2Cap {
+    #[inline]
+    fn get(self) -> usize {
+        self.0
+    }
+}
+
+/// The sending half of a queue whose capacity is part of its type.
+///
+/// It derefs to the wrapped [`spsc::Sender`](super::Sender).
+#[derive(Debug)]
+pub struct Sender<T, C: Capacity> {
+    tx: super::Sender<T>,
+    cap: C,
+}
+
+impl<T, C: Capacity> Sender<T, C> {
+    /// Sends an element like [`spsc::Sender::try_send`](super::Sender::try_send).
+    pub fn try_send(&mut self, el: T) -> Result<(), TrySendError<T>> {
+        let mask = self.cap.get() - 1;
+        let shared = unsafe { self.tx.ptr.as_ref() };
+        if !shared.is_present(RX, Ordering::Relaxed) {
+            return Err(TrySendError::Disconnected(el));
+        }
+        let head = shared.meta.head.load(Ordering::Relaxed);
+        let tail = shared.meta.tail.load(Ordering::Acquire);
+        // Positions of a power-of-two queue wrap with `usize`, so there is
+        // nothing to check but the mask.
+        if head.wrapping_sub(tail) > mask {
+            self.tx.note_full();
+            return Err(TrySendError::Full(el));
+        }
+        unsafe {
+            shared.slots().add(head & mask).write(el);
+            self.tx.publish_to(head.wrapping_add(1), 1);
+        }
+        Ok(())
+    }
+
+    /// Unwraps the sender.
+    pub fn into_inner(self) -> super::Sender<T> {
+        self.tx
+    }
+}
+
+impl<T, C: Capacity> Deref for Sender<T, C> {
+    type Target = super::Sender<T>;
+
+    fn deref(&self) -> &super::Sender<T> {
+        &self.tx
+    }
+}
+
+impl<T, C: Capacity> DerefMut for Sender<T, C> {
+    fn deref_mut(&mut self) -> &mut super::Sender<T> {
+        &mut self.tx
+    }
+}
+
+/// The receiving half of a queue whose capacity is part of its type.
+///
+/// It derefs to the wrapped [`spsc::Receiver`](super::Receiver).
+#[derive(Debug)]
+pub struct Receiver<T, C: Capacity> {
+    rx: super::Receiver<T>,
+    cap: C,
+}
+
+impl<T, C: Capacity> Receiver<T, C> {
+    /// Receives an element like
+    /// [`spsc::Receiver::try_recv`](super::Receiver::try_recv).
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mask = self.cap.get() - 1;
+        let tail = self.rx.front()?;
+        let shared = unsafe { self.rx.ptr.as_ref() };
+        let el = unsafe { shared.slots().add(tail & mask).read() };
+        self.rx.pop_front_to(tail.wrapping_add(1), 1);
+        Ok(el)
+    }
+
+    /// Unwraps the receiver.
+    pub fn into_inner(self) -> super::Receiver<T> {
+        self.rx
+    }
+}
+
+impl<T, C: Capacity> Deref for Receiver<T, C> {
+    type Target = super::Receiver<T>;
+
+    fn deref(&self) -> &super::Receiver<T> {
+        &self.rx
+    }
+}
+
+impl<T, C: Capacity> DerefMut for Receiver<T, C> {
+    fn deref_mut(&mut self) -> &mut super::Receiver<T> {
+        &mut self.rx
+    }
+}
+
+pub(super) fn split<T, C: Capacity>(cap: C) -> (Sender<T, C>, Receiver<T, C>) {
+    let (tx, rx) = super::new(cap.get());
+    let meta = unsafe { &tx.ptr.as_ref().meta };
+    // What the unconditional mask relies on, so the buffer stays put.
+    debug_assert!(meta.wrap == 0 && meta.mask == Some(cap.get() - 1));
+    meta.state.fetch_or(FIXED, Ordering::Relaxed);
+    (Sender { tx, cap }, Receiver { rx, cap })
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use std::prelude::rust_2024::*;
+
+    use crate::spsc::{TryRecvError, TrySendError, new_const};
+    use crate::sync::Ordering;
+
+    #[test]
+    fn const_cap() {
+        let (mut tx, mut rx) = new_const::<4, String>();
+        // Start right before the positions wrap around `usize`.
+        let meta = unsafe { &tx.tx.ptr.as_ref().meta };
+        meta.head.store(usize::MAX - 2, Ordering::Relaxed);
+        meta.tail.store(usize::MAX - 2, Ordering::Relaxed);
+        for lap in 0..3 {
+            for i in 0..4 {
+                tx.try_send(format!("{lap}.{i}")).unwrap();
+            }
+            assert_eq!(tx.try_send("x".into()), Err(TrySendError::Full("x".into())));
+            assert_eq!(rx.len(), 4);
+            for i in 0..4 {
+                assert_eq!(rx.try_recv().unwrap(), format!("{lap}.{i}"));
+            }
+            assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        }
+
+        // The plain handles underneath see the same queue.
+        tx.try_send("a".into()).unwrap();
+        tx.send_iter(["b".into()]);
+        assert_eq!(rx.recv_many(1), ["a"]);
+        assert_eq!(rx.try_recv().as_deref(), Ok("b"));
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn receiver_gone() {
+        let (mut tx, rx) = new_const::<2, u8>();
+        drop(rx.into_inner());
+        assert_eq!(tx.try_send(1), Err(TrySendError::Disconnected(1)));
+    }
+}
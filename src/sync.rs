@@ -0,0 +1,22 @@
+// Atomics used by the `spsc` protocol, swapped for loom's model-checked ones
+// when building with `--cfg loom`.
+
+#[cfg(all(loom, feature = "metrics"))]
+pub(crate) use loom::sync::atomic::AtomicBool;
+#[cfg(all(loom, feature = "heartbeat"))]
+pub(crate) use loom::sync::atomic::AtomicU64;
+#[cfg(loom)]
+pub(crate) use loom::{
+    hint::spin_loop,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering, fence},
+};
+
+#[cfg(all(not(loom), feature = "metrics"))]
+pub(crate) use core::sync::atomic::AtomicBool;
+#[cfg(all(not(loom), feature = "heartbeat"))]
+pub(crate) use core::sync::atomic::AtomicU64;
+#[cfg(not(loom))]
+pub(crate) use core::{
+    hint::spin_loop,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering, fence},
+};
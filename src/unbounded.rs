@@ -0,0 +1,190 @@
+use crate::spsc;
+
+#[cfg(feature = "std")]
+pub use crate::error::RecvError;
+pub use crate::error::{SendError, TryRecvError};
+
+// Segments are plain bounded queues. When one fills up, the sender opens a
+// twice as large one and sends its receiver down the old one, so the
+// receiver moves over once it has drained everything sent before.
+//
+// Nothing is ever swapped out from under a handle: each end owns a handle
+// to exactly one segment, and a segment the receiver hasn't reached yet is
+// owned by the `Next` message leading to it. Dropping either end therefore
+// frees whatever segments are left through the ordinary spsc teardown.
+enum Msg<T> {
+    Item(T),
+    Next(spsc::Receiver<Msg<T>>),
+}
+
+impl<T> core::fmt::Debug for Msg<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Msg::Item(..) => f.write_str("Item(..)"),
+            Msg::Next(..) => f.write_str("Next(..)"),
+        }
+    }
+}
+
+/// The sending half of an [`unbounded`](new) queue.
+#[derive(Debug)]
+pub struct Sender<T> {
+    tx: spsc::Sender<Msg<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends an element, growing the queue if it is full.
+    ///
+    /// Fails only if the receiver is gone.
+    pub fn try_send(&mut self, el: T) -> Result<(), SendError<T>> {
+        // The last slot of a segment is kept for the link to the next one.
+        if self.tx.len() + 1 >= self.tx.capacity() {
+            let (tx, rx) = spsc::new(self.tx.capacity() * 2);
+            if self.tx.try_send(Msg::Next(rx)).is_err() {
+                return Err(SendError(el));
+            }
+            // Dropping the old sender lets the old segment be freed as soon
+            // as the receiver is done with it.
+            self.tx = tx;
+        }
+        match self.tx.try_send(Msg::Item(el)) {
+            Ok(()) => Ok(()),
+            Err(e) => match e.into_inner() {
+                Msg::Item(el) => Err(SendError(el)),
+                Msg::Next(_) => unreachable!(),
+            },
+        }
+    }
+
+    /// Returns the capacity of the segment currently being filled.
+    pub fn capacity(&self) -> usize {
+        self.tx.capacity() - 1
+    }
+}
+
+/// The receiving half of an [`unbounded`](new) queue.
+#[derive(Debug)]
+pub struct Receiver<T> {
+    rx: spsc::Receiver<Msg<T>>,
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        loop {
+            match self.rx.try_recv()? {
+                Msg::Item(el) => return Ok(el),
+                Msg::Next(rx) => self.rx = rx,
+            }
+        }
+    }
+
+    /// Receives an element, blocking while the queue is empty.
+    ///
+    /// Fails once the queue is empty and the sender is gone.
+    #[cfg(feature = "std")]
+    pub fn recv(&mut self) -> Result<T, RecvError> {
+        loop {
+            match self.rx.recv()? {
+                Msg::Item(el) => return Ok(el),
+                Msg::Next(rx) => self.rx = rx,
+            }
+        }
+    }
+}
+
+/// Creates a queue that never reports full, starting with room for `cap`
+/// elements and doubling whenever it fills up.
+///
+/// Space is only given back once the receiver has moved past a full
+/// segment.
+///
+/// # Panics
+///
+/// Panics if `cap` is zero.
+pub fn new<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(cap > 0, "queue capacity must be greater than zero");
+    let (tx, rx) = spsc::new(cap + 1);
+    (Sender { tx }, Receiver { rx })
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use std::prelude::rust_2024::*;
+
+    use crate::unbounded::{SendError, TryRecvError, new};
+
+    #[test]
+    fn grow() {
+        let (mut tx, mut rx) = new(1);
+        for i in 0..100 {
+            tx.try_send(i).unwrap();
+        }
+        assert!(tx.capacity() > 1);
+        for i in 0..100 {
+            assert_eq!(rx.try_recv(), Ok(i));
+        }
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+
+        let (mut tx, rx) = new(1);
+        drop(rx);
+        assert_eq!(tx.try_send(1), Err(SendError(1)));
+    }
+
+    #[test]
+    fn drop_grown() {
+        use std::sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        };
+
+        struct DropCounter(Arc<AtomicUsize>);
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        for sender_first in [true, false] {
+            let (mut tx, mut rx) = new(1);
+            for _ in 0..50 {
+                tx.try_send(DropCounter(drops.clone())).unwrap();
+            }
+            // The receiver is a few segments behind the sender.
+            for _ in 0..5 {
+                rx.try_recv().unwrap();
+            }
+            assert!(tx.capacity() > 16);
+            if sender_first {
+                drop((tx, rx));
+            } else {
+                drop((rx, tx));
+            }
+        }
+        // Every element was dropped once, and Miri checks that every
+        // segment was freed.
+        assert_eq!(drops.load(Ordering::Relaxed), 100);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn grow_while_receiving() {
+        use std::thread;
+
+        const N: usize = if cfg!(miri) { 500 } else { 100_000 };
+        let (mut tx, mut rx) = new(1);
+        let handle = thread::spawn(move || {
+            for i in 0..N {
+                tx.try_send(i).unwrap();
+            }
+        });
+        for i in 0..N {
+            assert_eq!(rx.recv(), Ok(i));
+        }
+        assert!(rx.recv().is_err());
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+use core::{cell::UnsafeCell, task::Waker};
+
+#[cfg(feature = "std")]
+use alloc::{sync::Arc, task::Wake};
+#[cfg(feature = "std")]
+use std::{
+    thread::{self, Thread},
+    time::Instant,
+};
+
+use crate::sync::{AtomicUsize, Ordering, fence};
+
+// No waker is stored.
+const EMPTY: usize = 0;
+// The waiting side is writing its waker into the slot.
+#[cfg(any(feature = "std", feature = "async"))]
+const REGISTERING: usize = 1;
+// A waker is stored and may be taken by a notifier.
+const WAITING: usize = 2;
+// A notifier is taking the stored waker.
+const WAKING: usize = 4;
+
+/// A slot holding the waker of whoever is waiting on one side of the queue.
+///
+/// Only one party registers at a time. The waiting side must re-check its
+/// condition after [`register`](Self::register) and before sleeping; the
+/// notifying side must call [`wake`](Self::wake) after publishing the change
+/// the waiter is looking for. The `SeqCst` fences on both sides make sure that
+/// at least one of them sees the other's write, so no wakeup is lost.
+pub(crate) struct AtomicWaker {
+    state: AtomicUsize,
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+impl AtomicWaker {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(EMPTY),
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    #[cfg(any(feature = "std", feature = "async"))]
+    pub(crate) fn register(&self, waker: &Waker) {
+        let state = self.state.load(Ordering::Acquire);
+        if state & WAKING != 0
+            || self
+                .state
+                .compare_exchange(state, REGISTERING, Ordering::Acquire, Ordering::Acquire)
+                .is_err()
+        {
+            // A notifier is already at work, so whatever we were waiting for
+            // has probably happened. Let the caller re-check right away.
+            waker.wake_by_ref();
+            return;
+        }
+
+        // SAFETY: `REGISTERING` gives us exclusive access to the slot.
+        let slot = unsafe { &mut *self.waker.get() };
+        if !slot.as_ref().is_some_and(|w| w.will_wake(waker)) {
+            *slot = Some(waker.clone());
+        }
+
+        if self
+            .state
+            .compare_exchange(REGISTERING, WAITING, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            // A notifier showed up while we were registering and left the
+            // wakeup to us.
+            let waker = slot.take();
+            self.state.store(EMPTY, Ordering::Release);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+        fence(Ordering::SeqCst);
+    }
+
+    pub(crate) fn wake(&self) {
+        fence(Ordering::SeqCst);
+        if self.state.load(Ordering::Relaxed) == EMPTY {
+            return;
+        }
+        match self.state.fetch_or(WAKING, Ordering::AcqRel) {
+            WAITING => {
+                // SAFETY: moving the state from `WAITING` to `WAKING` gives us
+                // exclusive access to the slot.
+                let waker = unsafe { (*self.waker.get()).take() };
+                self.state.store(EMPTY, Ordering::Release);
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            }
+            EMPTY => {
+                self.state.fetch_and(!WAKING, Ordering::Release);
+            }
+            // Either the waiter is mid-registration and will wake itself, or
+            // another notifier got here first.
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+struct Unparker(Thread);
+
+#[cfg(feature = "std")]
+impl Wake for Unparker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Calls `op` until it returns `Some`. The first `spins` retries busy-wait,
+/// after that the current thread parks on `slots` between attempts, waking up
+/// when any of them is notified. Without an
+/// explicit count, the `backoff` feature spins and yields following
+/// `crossbeam_utils::Backoff` before parking.
+///
+/// Gives up and returns `None` once `deadline` has passed.
+#[cfg(feature = "std")]
+pub(crate) fn block_on<R>(
+    slots: &[&AtomicWaker],
+    spins: Option<u32>,
+    deadline: Option<Instant>,
+    mut op: impl FnMut() -> Option<R>,
+) -> Option<R> {
+    if let Some(r) = op() {
+        return Some(r);
+    }
+    match spins {
+        Some(spins) => {
+            for _ in 0..spins {
+                core::hint::spin_loop();
+                if let Some(r) = op() {
+                    return Some(r);
+                }
+            }
+        }
+        #[cfg(feature = "backoff")]
+        None => {
+            let backoff = crossbeam_utils::Backoff::new();
+            while !backoff.is_completed() {
+                backoff.snooze();
+                if let Some(r) = op() {
+                    return Some(r);
+                }
+            }
+        }
+        #[cfg(not(feature = "backoff"))]
+        None => {}
+    }
+    let waker = Waker::from(Arc::new(Unparker(thread::current())));
+    loop {
+        for slot in slots {
+            slot.register(&waker);
+        }
+        if let Some(r) = op() {
+            return Some(r);
+        }
+        match deadline {
+            None => thread::park(),
+            Some(deadline) => {
+                // Wakeups can be spurious, so the remaining time is worked out
+                // again on every round.
+                let now = Instant::now();
+                if now >= deadline {
+                    return None;
+                }
+                thread::park_timeout(deadline - now);
+            }
+        }
+    }
+}
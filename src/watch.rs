@@ -0,0 +1,58 @@
+use crate::overwrite;
+
+/// Publishes values to a [`Receiver`] that only cares about the latest one.
+#[derive(Debug)]
+pub struct Sender<T> {
+    inner: overwrite::Sender<T>,
+}
+
+impl<T> Sender<T> {
+    /// Replaces the current value with `value`.
+    ///
+    /// A previous value the receiver hasn't read yet is dropped.
+    pub fn set(&mut self, value: T) {
+        drop(self.inner.force_send(value));
+    }
+}
+
+/// Reads the latest value published by a [`Sender`].
+#[derive(Debug)]
+pub struct Receiver<T> {
+    inner: overwrite::Receiver<T>,
+}
+
+impl<T> Receiver<T> {
+    /// Takes the latest value, or returns `None` if nothing was set since the
+    /// last call.
+    pub fn get(&mut self) -> Option<T> {
+        self.inner.try_recv().ok()
+    }
+}
+
+/// Creates a channel that holds a single value, which every
+/// [`set`](Sender::set) replaces.
+pub fn new<T>() -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = overwrite::new(1);
+    (Sender { inner: tx }, Receiver { inner: rx })
+}
+
+#[cfg(test)]
+mod test {
+    use std::prelude::rust_2024::*;
+
+    use crate::watch::new;
+
+    #[test]
+    fn latest() {
+        let (mut tx, mut rx) = new();
+        assert_eq!(rx.get(), None);
+        for v in ["a", "b", "c"] {
+            tx.set(v.to_string());
+        }
+        assert_eq!(rx.get().as_deref(), Some("c"));
+        // Nothing changed since.
+        assert_eq!(rx.get(), None);
+        tx.set("d".to_string());
+        assert_eq!(rx.get().as_deref(), Some("d"));
+    }
+}
@@ -0,0 +1,7 @@
+#[test]
+#[cfg_attr(miri, ignore = "spawns the compiler")]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/fail/*.rs");
+    t.pass("tests/ui/pass/*.rs");
+}
@@ -0,0 +1,6 @@
+fn assert_sync<T: Sync>(_: &T) {}
+
+fn main() {
+    let (tx, _rx) = queue::spsc::new::<u8>(1);
+    assert_sync(&tx);
+}
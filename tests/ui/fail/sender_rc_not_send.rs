@@ -0,0 +1,8 @@
+use std::rc::Rc;
+
+fn assert_send<T: Send>(_: T) {}
+
+fn main() {
+    let (tx, _rx) = queue::spsc::new::<Rc<u8>>(1);
+    assert_send(tx);
+}
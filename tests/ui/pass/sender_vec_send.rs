@@ -0,0 +1,7 @@
+fn assert_send<T: Send>(_: T) {}
+
+fn main() {
+    let (tx, rx) = queue::spsc::new::<Vec<u8>>(1);
+    assert_send(tx);
+    assert_send(rx);
+}